@@ -0,0 +1,182 @@
+use glob::glob;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::errors::SAError;
+
+// Where the hash of each command's inputs is kept after it succeeds, to tell if they have changed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Cache {
+        Cache {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self, shortcut: &str, key: &str) -> PathBuf {
+        self.dir.join(shortcut).join(key)
+    }
+
+    pub fn get(&self, shortcut: &str, key: &str) -> Option<String> {
+        fs::read_to_string(self.path(shortcut, key))
+            .ok()
+            .map(|hash| hash.trim().to_string())
+    }
+
+    pub fn set(&self, shortcut: &str, key: &str, hash: &str) -> std::io::Result<()> {
+        let path: PathBuf = self.path(shortcut, key);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        };
+        fs::write(path, hash)
+    }
+}
+
+fn matches(pattern: &str) -> Result<Vec<PathBuf>, SAError> {
+    Ok(glob(pattern)
+        .map_err(SAError::GlobFailure)?
+        .filter_map(|path| path.ok())
+        .collect())
+}
+
+// Every file matching the patterns, sorted so the hash doesn't depend on the order they are found.
+pub fn find_files(patterns: &[String]) -> Result<Vec<PathBuf>, SAError> {
+    let mut files: Vec<PathBuf> = Vec::new();
+
+    for pattern in patterns.iter() {
+        files.extend(matches(pattern)?.into_iter().filter(|path| path.is_file()));
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+// Hash the command, along with the path and content of every input, so a change to any of them
+// is noticed.
+pub fn hash_inputs(command: &str, inputs: &[PathBuf]) -> Result<String, SAError> {
+    let mut hasher = Sha256::new();
+    hasher.update(command.as_bytes());
+
+    for path in inputs.iter() {
+        let content: Vec<u8> = fs::read(path)
+            .map_err(|e| SAError::Inputs(format!("Couldn't read '{}': {}", path.display(), e)))?;
+
+        hasher.update([0]);
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(&content);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Whether every output pattern matches at least one path.
+pub fn outputs_exist(outputs: &[String]) -> Result<bool, SAError> {
+    for pattern in outputs.iter() {
+        if matches(pattern)?.is_empty() {
+            return Ok(false);
+        };
+    }
+
+    Ok(true)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// Whether every output was changed after every input, for commands which haven't been cached yet.
+pub fn outputs_newer(inputs: &[PathBuf], outputs: &[String]) -> Result<bool, SAError> {
+    let mut output_paths: Vec<PathBuf> = Vec::new();
+    for pattern in outputs.iter() {
+        output_paths.extend(matches(pattern)?);
+    }
+
+    let newest_input: Option<SystemTime> = inputs.iter().filter_map(|path| modified(path)).max();
+    let oldest_output: Option<SystemTime> = output_paths
+        .iter()
+        .map(|path| modified(path))
+        .min()
+        .flatten();
+
+    Ok(match (newest_input, oldest_output) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(input), Some(output)) => output >= input,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{find_files, hash_inputs, outputs_exist, outputs_newer, Cache};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_cache() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let cache = Cache::new(&tmp_dir.path().join("cache"));
+
+        assert_eq!(cache.get("build", "compile"), None);
+        cache
+            .set("build", "compile", "abc123")
+            .expect("Couldn't write to cache.");
+        assert_eq!(cache.get("build", "compile"), Some("abc123".to_string()));
+        assert_eq!(cache.get("build", "test"), None);
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+
+    #[test]
+    fn test_inputs_and_outputs() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let dir: String = tmp_dir.path().display().to_string();
+        fs::create_dir(tmp_dir.path().join("src")).expect("Couldn't create folder.");
+        fs::write(tmp_dir.path().join("src/b.rs"), "b").expect("Couldn't write file.");
+        fs::write(tmp_dir.path().join("src/a.rs"), "a").expect("Couldn't write file.");
+
+        let inputs: Vec<PathBuf> =
+            find_files(&[format!("{dir}/src/*.rs"), format!("{dir}/src/a.rs")])
+                .expect("Couldn't find inputs.");
+        assert_eq!(
+            inputs,
+            vec![
+                tmp_dir.path().join("src/a.rs"),
+                tmp_dir.path().join("src/b.rs")
+            ]
+        );
+
+        let hash: String = hash_inputs("cargo build", &inputs).expect("Couldn't hash inputs.");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash_inputs("cargo build", &inputs).unwrap(), hash);
+        assert_ne!(hash_inputs("cargo test", &inputs).unwrap(), hash);
+
+        fs::write(tmp_dir.path().join("src/a.rs"), "changed").expect("Couldn't write file.");
+        assert_ne!(hash_inputs("cargo build", &inputs).unwrap(), hash);
+
+        let outputs: Vec<String> = vec![format!("{dir}/out/*")];
+        assert!(!outputs_exist(&outputs).unwrap());
+        assert!(!outputs_newer(&inputs, &outputs).unwrap());
+
+        fs::create_dir(tmp_dir.path().join("out")).expect("Couldn't create folder.");
+        fs::write(tmp_dir.path().join("out/app"), "app").expect("Couldn't write file.");
+        assert!(outputs_exist(&outputs).unwrap());
+        assert!(outputs_newer(&inputs, &outputs).unwrap());
+        assert!(outputs_exist(&[]).unwrap());
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+}
@@ -5,7 +5,7 @@ use std::fs::create_dir;
 use std::path::{Path, PathBuf};
 
 use crate::errors::SAError;
-use crate::shortcut::Shortcut;
+use crate::shortcut::{resolve_extends, validate_runs, Shortcut};
 
 pub fn discover_commands(mut folder: String) -> Result<HashMap<String, Shortcut>, SAError> {
     let mut shortcuts: HashMap<String, Shortcut> = HashMap::new();
@@ -29,13 +29,29 @@ pub fn discover_commands(mut folder: String) -> Result<HashMap<String, Shortcut>
     let files: Vec<PathBuf> = files.into_iter().filter_map(|path| path.ok()).collect();
 
     for path in files {
+        // Hidden files and folders, such as the settings file, are not shortcuts.
+        if is_hidden(&path, &folder) {
+            continue;
+        };
+
         let shortcut_file = Shortcut::new(&path)?;
         shortcuts.insert(shortcut_file.name.to_owned(), shortcut_file);
     }
 
+    resolve_extends(&mut shortcuts)?;
+    validate_runs(&shortcuts)?;
+
     Ok(shortcuts)
 }
 
+fn is_hidden(path: &Path, folder: &str) -> bool {
+    let relative: &Path = path.strip_prefix(folder).unwrap_or(path);
+
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
 pub fn discover_config_dir() -> String {
     let directory: String;
 
@@ -61,6 +77,74 @@ pub fn discover_config_dir() -> String {
     directory
 }
 
+// Run state sits next to the config directory, the same as the cache.
+pub fn discover_state_dir(config_dir: &str) -> PathBuf {
+    if let Ok(dir) = env::var("SHORTCUT_ALIAS_STATE") {
+        PathBuf::from(dir)
+    } else if let Ok(dir) = env::var("SA_STATE") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(format!(
+            "{}-state",
+            config_dir.trim_end_matches(['/', '\\'])
+        ))
+    }
+}
+
+// The cache sits next to the config directory, rather than inside it, so it is never loaded as shortcuts.
+pub fn discover_cache_dir(config_dir: &str) -> PathBuf {
+    if let Ok(dir) = env::var("SHORTCUT_ALIAS_CACHE") {
+        PathBuf::from(dir)
+    } else if let Ok(dir) = env::var("SA_CACHE") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(format!(
+            "{}-cache",
+            config_dir.trim_end_matches(['/', '\\'])
+        ))
+    }
+}
+
+// The name of the built in command for listing previous runs.
+pub const HISTORY: &str = "history";
+
+fn history_command() -> clap::Command {
+    clap::Command::new(HISTORY)
+        .about("List previous runs of shortcuts, or run one again.")
+        .arg(
+            clap::Arg::new("shortcut")
+                .action(clap::ArgAction::Set)
+                .long("shortcut")
+                .required(false)
+                .help("Only list runs of this shortcut."),
+        )
+        .arg(
+            clap::Arg::new("status")
+                .action(clap::ArgAction::Set)
+                .long("status")
+                .required(false)
+                .value_parser(["succeeded", "failed"])
+                .help("Only list runs which succeeded or failed."),
+        )
+        .arg(
+            clap::Arg::new("limit")
+                .action(clap::ArgAction::Set)
+                .long("limit")
+                .default_value("20")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .help("The most runs to list, starting from the most recent."),
+        )
+        .arg(
+            clap::Arg::new("rerun")
+                .action(clap::ArgAction::Set)
+                .long("rerun")
+                .value_name("NUMBER")
+                .required(false)
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .help("Run the run with this number again, with the same arguments."),
+        )
+}
+
 pub fn build_cli(shortcuts: Vec<&Shortcut>) -> clap::Command {
     let mut cli = clap::Command::new("shortcut-alias")
         .about("A powerful alias tool.")
@@ -102,6 +186,14 @@ pub fn build_cli(shortcuts: Vec<&Shortcut>) -> clap::Command {
                 .required(false)
                 .help("Set to 'off' to not show the command footer."),
         )
+        .arg(
+            clap::Arg::new("summary")
+                .action(clap::ArgAction::Set)
+                .long("summary")
+                .default_value("on")
+                .required(false)
+                .help("Set to 'off' to not show the summary of steps after a run."),
+        )
         .arg(
             clap::Arg::new("silent")
                 .action(clap::ArgAction::SetTrue)
@@ -109,29 +201,120 @@ pub fn build_cli(shortcuts: Vec<&Shortcut>) -> clap::Command {
                 .short('s')
                 .required(false)
                 .help("Set to suppress all output."),
+        )
+        .arg(
+            clap::Arg::new("output")
+                .action(clap::ArgAction::Set)
+                .long("output")
+                .default_value("text")
+                .value_parser(["text", "json", "jsonl"])
+                .required(false)
+                .help("Set to 'json' or 'jsonl' to print the run as JSON events."),
+        )
+        .arg(
+            clap::Arg::new("shell")
+                .action(clap::ArgAction::Set)
+                .long("shell")
+                .required(false)
+                .help("The shell used to run commands. Defaults to $SHELL or /bin/sh."),
+        )
+        .arg(
+            clap::Arg::new("dry_run")
+                .action(clap::ArgAction::SetTrue)
+                .long("dry-run")
+                .short('n')
+                .required(false)
+                .help("Show the commands which would be run, without running them."),
+        )
+        .arg(
+            clap::Arg::new("yes")
+                .action(clap::ArgAction::SetTrue)
+                .long("yes")
+                .short('y')
+                .required(false)
+                .help("Answer yes to any confirmation prompts."),
+        )
+        .arg(
+            clap::Arg::new("jobs")
+                .action(clap::ArgAction::Set)
+                .long("jobs")
+                .short('j')
+                .required(false)
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .help("The most commands to run at once, for shortcuts using 'needs'."),
+        )
+        .arg(
+            clap::Arg::new("force")
+                .action(clap::ArgAction::SetTrue)
+                .long("force")
+                .required(false)
+                .help("Run commands with 'inputs' or 'outputs', even if they are up to date."),
+        )
+        .arg(
+            clap::Arg::new("resume")
+                .action(clap::ArgAction::Set)
+                .long("resume")
+                .value_name("SHORTCUT")
+                .required(false)
+                .conflicts_with_all(["from", "only"])
+                .help("Run a shortcut again from the step which failed last time."),
+        )
+        .arg(
+            clap::Arg::new("from")
+                .action(clap::ArgAction::Set)
+                .long("from")
+                .value_name("STEP")
+                .required(false)
+                .conflicts_with("only")
+                .help("Start the shortcut from this step, using the last run's results for earlier steps."),
+        )
+        .arg(
+            clap::Arg::new("only")
+                .action(clap::ArgAction::Set)
+                .long("only")
+                .value_name("STEP")
+                .required(false)
+                .help("Only run this step, using the last run's results for the others."),
         );
 
+    // Shortcuts take priority over built in commands with the same name.
+    let has_history: bool = shortcuts.iter().any(|shortcut| shortcut.name == HISTORY);
+
     for config in shortcuts {
         cli = cli.subcommand(config.command());
     }
 
+    if !has_history {
+        cli = cli.subcommand(history_command());
+    };
+
     cli
 }
 
+// These tests check flags with `assert_eq!(.., true)` and walk iterators with `into_iter()`.
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::useless_conversion)]
 mod tests {
-    use crate::cli::{build_cli, discover_commands, discover_config_dir};
+    use crate::cli::{
+        build_cli, discover_cache_dir, discover_commands, discover_config_dir, discover_state_dir,
+    };
     use crate::shortcut::{Argument, ArgumentType, Command, Shortcut};
     use std::collections::HashMap;
     use std::fs::File;
     use std::io::Write;
+    use std::path::PathBuf;
     use tempdir::TempDir;
     use tmp_env::set_var;
 
     #[test]
     fn test_build_cli_no_shortcuts() {
         let cli = build_cli(vec![]);
-        assert_eq!(cli.has_subcommands(), false);
+        assert_eq!(
+            cli.get_subcommands()
+                .map(|subcommand| subcommand.get_name())
+                .collect::<Vec<&str>>(),
+            vec!["history"]
+        );
         assert_eq!(cli.get_name(), "shortcut-alias");
         assert_eq!(cli.get_version(), Some(env!("CARGO_PKG_VERSION")));
 
@@ -157,11 +340,58 @@ mod tests {
         assert_eq!(item.get_long(), Some("footer"));
         assert_eq!(item.get_short(), Some('f'));
 
+        let item = options_iter.next().expect("summary Arg not configured.");
+        assert_eq!(item.get_id(), "summary");
+        assert_eq!(item.get_long(), Some("summary"));
+        assert_eq!(item.get_short(), None);
+
         let item = options_iter.next().expect("silent Arg not configured.");
         assert_eq!(item.get_id(), "silent");
         assert_eq!(item.get_long(), Some("silent"));
         assert_eq!(item.get_short(), Some('s'));
 
+        let item = options_iter.next().expect("output Arg not configured.");
+        assert_eq!(item.get_id(), "output");
+        assert_eq!(item.get_long(), Some("output"));
+        assert_eq!(item.get_short(), None);
+
+        let item = options_iter.next().expect("shell Arg not configured.");
+        assert_eq!(item.get_id(), "shell");
+        assert_eq!(item.get_long(), Some("shell"));
+        assert_eq!(item.get_short(), None);
+
+        let item = options_iter.next().expect("dry_run Arg not configured.");
+        assert_eq!(item.get_id(), "dry_run");
+        assert_eq!(item.get_long(), Some("dry-run"));
+        assert_eq!(item.get_short(), Some('n'));
+
+        let item = options_iter.next().expect("yes Arg not configured.");
+        assert_eq!(item.get_id(), "yes");
+        assert_eq!(item.get_long(), Some("yes"));
+        assert_eq!(item.get_short(), Some('y'));
+
+        let item = options_iter.next().expect("jobs Arg not configured.");
+        assert_eq!(item.get_id(), "jobs");
+        assert_eq!(item.get_long(), Some("jobs"));
+        assert_eq!(item.get_short(), Some('j'));
+
+        let item = options_iter.next().expect("force Arg not configured.");
+        assert_eq!(item.get_id(), "force");
+        assert_eq!(item.get_long(), Some("force"));
+        assert_eq!(item.get_short(), None);
+
+        let item = options_iter.next().expect("resume Arg not configured.");
+        assert_eq!(item.get_id(), "resume");
+        assert_eq!(item.get_long(), Some("resume"));
+
+        let item = options_iter.next().expect("from Arg not configured.");
+        assert_eq!(item.get_id(), "from");
+        assert_eq!(item.get_long(), Some("from"));
+
+        let item = options_iter.next().expect("only Arg not configured.");
+        assert_eq!(item.get_id(), "only");
+        assert_eq!(item.get_long(), Some("only"));
+
         assert!(options_iter.next().is_none());
     }
 
@@ -178,14 +408,20 @@ mod tests {
                     name: "example".to_string(),
                     default: Some("no".to_string()),
                     help: Some("Some Help Text".to_string()),
+                    ..Default::default()
                 }]),
                 env: None,
                 variables: None,
+                autoescape: None,
+                undefined: None,
+                confirm: None,
                 commands: vec![Command {
                     name: "ExampleCommand".to_string(),
                     description: None,
-                    command: "echo 'HelloWorld!'".to_string(),
+                    command: Some("echo 'HelloWorld!'".to_string()),
+                    ..Default::default()
                 }],
+                ..Default::default()
             },
         );
 
@@ -233,6 +469,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_discover_cache_dir() {
+        assert_eq!(
+            discover_cache_dir("/home/user/.shortcut/"),
+            PathBuf::from("/home/user/.shortcut-cache")
+        );
+
+        let _tmp_env = set_var("SA_CACHE", "/some/cache/dir");
+        assert_eq!(
+            discover_cache_dir("/home/user/.shortcut"),
+            PathBuf::from("/some/cache/dir")
+        );
+    }
+
+    #[test]
+    fn test_discover_state_dir() {
+        assert_eq!(
+            discover_state_dir("/home/user/.shortcut"),
+            PathBuf::from("/home/user/.shortcut-state")
+        );
+
+        let _tmp_env = set_var("SA_STATE", "/some/state/dir");
+        assert_eq!(
+            discover_state_dir("/home/user/.shortcut"),
+            PathBuf::from("/some/state/dir")
+        );
+    }
+
     #[test]
     fn test_discover_commands() {
         // Setup tempdir
@@ -247,6 +511,11 @@ mod tests {
         )
         .expect("Couldn't write to temporary file.");
 
+        // Hidden files, such as the settings file, should not be loaded as shortcuts.
+        let mut settings_file = File::create(tmp_dir.path().join(".settings.yaml"))
+            .expect("Couldn't create temporary file.");
+        writeln!(settings_file, "shell: /bin/sh").expect("Couldn't write to temporary file.");
+
         let _tmp_env = set_var("SHORTCUT_ALIAS_CONFIG", tmp_dir.path().as_os_str());
 
         // Perform Test
@@ -270,12 +539,26 @@ mod tests {
             vec![Command {
                 name: "Cargo Version".to_string(),
                 description: None,
-                command: "cargo -V".to_string()
+                command: Some("cargo -V".to_string()),
+                ..Default::default()
             }]
         );
 
+        assert_eq!(shortcuts.len(), 1);
+
+        // A file which isn't a shortcut is named in the error.
+        std::fs::create_dir(tmp_dir.path().join("vars"))
+            .expect("Couldn't create temporary folder.");
+        let vars_path = tmp_dir.path().join("vars").join("prod.yaml");
+        std::fs::write(&vars_path, "app: web\n").expect("Couldn't write to temporary file.");
+        match discover_commands(discover_config_dir()) {
+            Err(error) => assert!(error.to_string().contains(&vars_path.display().to_string())),
+            Ok(_) => panic!("A variables file was loaded as a shortcut."),
+        };
+
         // Cleanup
         drop(shortcut_file);
+        drop(settings_file);
         tmp_dir.close().expect("Couldn't close temp_dir.");
     }
 }
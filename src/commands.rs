@@ -1,62 +1,309 @@
 use serde::{Deserialize, Serialize};
-use std::process::{Command, Stdio};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wait_timeout::ChildExt;
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+use crate::errors::SAError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct CommandOutput {
+    // Stdout if the command succeeded, otherwise stderr.
     pub output: String,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
     pub status: i32,
+    pub data: Option<serde_json::Value>,
+    #[serde(default)]
+    pub timed_out: bool,
+    // Every time the command was run, including any retries.
+    #[serde(default)]
+    pub attempts: Vec<Attempt>,
+    // The results of the commands in a shortcut run by this command.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub commands: HashMap<String, CommandOutput>,
+    // How long the command took, including any retries.
+    #[serde(default, with = "seconds")]
+    pub duration: Duration,
 }
 
-fn output_as_string(output: Vec<u8>) -> Option<String> {
-    match String::from_utf8(output) {
-        Ok(s) => Some(s),
-        _ => None,
+// The result of a single run of a command, kept so earlier attempts can be looked at after a retry.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Attempt {
+    pub output: String,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    pub status: i32,
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(default, with = "seconds")]
+    pub duration: Duration,
+}
+
+impl From<&CommandOutput> for Attempt {
+    fn from(output: &CommandOutput) -> Attempt {
+        Attempt {
+            output: output.output.clone(),
+            stdout: output.stdout.clone(),
+            stderr: output.stderr.clone(),
+            status: output.status,
+            timed_out: output.timed_out,
+            duration: output.duration,
+        }
     }
 }
 
-#[cfg(target_family = "windows")]
-pub fn run_command(command: &str) -> CommandOutput {
-    let command = Command::new("pwsh")
-        .args(["-NoLogo", "-Command", command])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .expect("Couldn't Run Command");
+// Durations are stored as a number of seconds, so they can be compared and shown in templates.
+pub mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
 
-    let output: String = if command.status.success() {
-        output_as_string(command.stdout.to_vec()).unwrap()
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let seconds: f64 = f64::deserialize(deserializer)?;
+        Ok(Duration::try_from_secs_f64(seconds).unwrap_or_default())
+    }
+}
+
+// Show a duration to the hundredth of a second, or in minutes and seconds if it's longer.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds: f64 = duration.as_secs_f64();
+    if seconds < 60.0 {
+        format!("{:.2}s", seconds)
     } else {
-        output_as_string(command.stderr.to_vec()).unwrap()
+        format!(
+            "{}m {:02}s",
+            duration.as_secs() / 60,
+            duration.as_secs() % 60
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parse {
+    Json,
+    Yaml,
+    Lines,
+    Kv,
+}
+
+// Split `key=value` or `key: value` lines into a map. Blank lines, comments and other lines are skipped.
+fn parse_kv(output: &str) -> serde_json::Value {
+    let mut data = serde_json::Map::new();
+
+    for line in output.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        };
+
+        if let Some((key, value)) = line.split_once('=').or_else(|| line.split_once(':')) {
+            data.insert(
+                key.trim().to_string(),
+                serde_json::Value::String(value.trim().to_string()),
+            );
+        };
+    }
+
+    serde_json::Value::Object(data)
+}
+
+pub fn parse_output(output: &str, parse: Parse) -> Result<serde_json::Value, String> {
+    match parse {
+        Parse::Json => serde_json::from_str(output).map_err(|e| e.to_string()),
+        Parse::Yaml => serde_yaml::from_str(output).map_err(|e| e.to_string()),
+        Parse::Lines => Ok(serde_json::Value::from(
+            output.lines().map(String::from).collect::<Vec<String>>(),
+        )),
+        Parse::Kv => Ok(parse_kv(output)),
+    }
+}
+
+fn output_as_string(output: Vec<u8>) -> Option<String> {
+    String::from_utf8(output).ok()
+}
+
+// Work out the flag needed for the shell to run a command string.
+fn shell_args(shell: &str) -> Vec<&'static str> {
+    let shell_name: String = match Path::new(shell).file_stem() {
+        Some(stem) => stem.to_string_lossy().to_lowercase(),
+        None => String::new(),
     };
 
-    let status: i32 = command.status.code().unwrap_or(1);
+    match shell_name.as_str() {
+        "pwsh" | "powershell" => vec!["-NoLogo", "-Command"],
+        "cmd" => vec!["/C"],
+        _ => vec!["-c"],
+    }
+}
+
+// How long a timed out process has to exit after SIGTERM, before it is killed.
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+// The exit code recorded for a timed out process, matching the `timeout` command.
+pub const TIMED_OUT_STATUS: i32 = 124;
+
+// Read the whole pipe on another thread, so the process never blocks on a full pipe.
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer: Vec<u8> = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        };
+        buffer
+    })
+}
+
+// Stop a process which has run out of time, and everything it started.
+#[cfg(unix)]
+fn stop(child: &mut Child) -> std::io::Result<ExitStatus> {
+    // The process leads its own group, so the whole group is signalled.
+    let group: i32 = -(child.id() as i32);
+
+    unsafe { libc::kill(group, libc::SIGTERM) };
+    let status: Option<ExitStatus> = child.wait_timeout(KILL_GRACE)?;
+
+    // Anything left in the group, such as a process ignoring SIGTERM, would keep the output pipes
+    // open, so the group is always killed. It fails with ESRCH if nothing is left, which is fine.
+    unsafe { libc::kill(group, libc::SIGKILL) };
+
+    match status {
+        Some(status) => Ok(status),
+        None => child.wait(),
+    }
+}
 
-    CommandOutput { output, status }
+#[cfg(not(unix))]
+fn stop(child: &mut Child) -> std::io::Result<ExitStatus> {
+    child.kill()?;
+    child.wait()
 }
 
-#[cfg(target_family = "unix")]
-pub fn run_command(command: &str) -> CommandOutput {
-    let command = Command::new("dash")
-        .args(["-c", command])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
-        .expect("Couldn't Run Command");
+// Run the process to completion, capturing its output. If it runs longer than the timeout it is stopped.
+fn execute(
+    mut process: Command,
+    program: &str,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput, SAError> {
+    process.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    // Only put the process in its own group when it may need stopping, so Ctrl+C still reaches it otherwise.
+    #[cfg(unix)]
+    if timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        process.process_group(0);
+    };
 
-    let output: String = if command.status.success() {
-        output_as_string(command.stdout.to_vec()).unwrap()
+    let mut child: Child = match process.spawn() {
+        Ok(c) => c,
+        Err(e) => return Err(SAError::CommandSpawn(program.to_string(), e)),
+    };
+
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let waited = match timeout {
+        Some(timeout) => match child.wait_timeout(timeout) {
+            Ok(Some(status)) => Ok((status, false)),
+            Ok(None) => stop(&mut child).map(|status| (status, true)),
+            Err(e) => Err(e),
+        },
+        None => child.wait().map(|status| (status, false)),
+    };
+
+    let (status, timed_out) = match waited {
+        Ok(waited) => waited,
+        Err(e) => {
+            return Err(SAError::CommandFailed(format!(
+                "Couldn't wait for '{}': {}",
+                program, e
+            )))
+        }
+    };
+
+    // Output which isn't valid UTF-8 has the invalid bytes replaced, rather than failing the run.
+    let decode = |bytes: Vec<u8>| {
+        output_as_string(bytes.clone())
+            .unwrap_or_else(|| String::from_utf8_lossy(&bytes).into_owned())
+    };
+    let stdout: String = decode(stdout.join().unwrap_or_default());
+    let stderr: String = decode(stderr.join().unwrap_or_default());
+
+    let output: String = if status.success() {
+        stdout.clone()
     } else {
-        output_as_string(command.stderr.to_vec()).unwrap()
+        stderr.clone()
     };
 
-    let status: i32 = command.status.code().unwrap_or(1);
+    let status: i32 = if timed_out {
+        TIMED_OUT_STATUS
+    } else {
+        status.code().unwrap_or(1)
+    };
+
+    Ok(CommandOutput {
+        output,
+        stdout,
+        stderr,
+        status,
+        timed_out,
+        ..Default::default()
+    })
+}
+
+pub fn run_command(
+    shell: &str,
+    command: &str,
+    env: &HashMap<String, String>,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput, SAError> {
+    let mut process = Command::new(shell);
+    process.args(shell_args(shell)).arg(command).envs(env);
 
-    CommandOutput { output, status }
+    execute(process, shell, timeout)
+}
+
+// Run the program directly without a shell, so no quoting is needed for the arguments.
+pub fn run_argv(
+    argv: &[String],
+    env: &HashMap<String, String>,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput, SAError> {
+    let (program, args) = match argv.split_first() {
+        Some(parts) => parts,
+        None => {
+            return Err(SAError::CommandFailed(String::from(
+                "No program was given to run.",
+            )))
+        }
+    };
+
+    let mut process = Command::new(program);
+    process.args(args).envs(env);
+
+    execute(process, program, timeout)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::commands::{output_as_string, run_command, CommandOutput};
+    use crate::commands::{
+        format_duration, output_as_string, parse_output, run_argv, run_command, shell_args,
+        CommandOutput, Parse, TIMED_OUT_STATUS,
+    };
+    use crate::errors::SAError;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::time::Duration;
 
     #[test]
     fn test_output_as_string() {
@@ -79,7 +326,8 @@ mod tests {
     #[test]
     #[cfg(target_family = "windows")]
     fn test_run_command_win() {
-        let output: CommandOutput = run_command("Write-Host 'Hello World!'");
+        let output: CommandOutput =
+            run_command("pwsh", "Write-Host 'Hello World!'", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 0);
         assert_eq!(output.output, "Hello World!\r\n");
     }
@@ -87,11 +335,11 @@ mod tests {
     #[test]
     #[cfg(target_family = "windows")]
     fn test_run_command_errors_win() {
-        let output: CommandOutput = run_command("exit 1");
+        let output: CommandOutput = run_command("pwsh", "exit 1", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 1);
         assert_eq!(output.output, "");
 
-        let output = run_command("no-command");
+        let output = run_command("pwsh", "no-command", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 1);
         assert!(output
             .output
@@ -101,7 +349,8 @@ mod tests {
     #[test]
     #[cfg(target_family = "unix")]
     fn test_run_command_unix() {
-        let output: CommandOutput = run_command("echo 'Hello World!'");
+        let output: CommandOutput =
+            run_command("sh", "echo 'Hello World!'", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 0);
         assert_eq!(output.output, "Hello World!\n");
     }
@@ -109,12 +358,153 @@ mod tests {
     #[test]
     #[cfg(target_family = "unix")]
     fn test_run_command_errors_unix() {
-        let output: CommandOutput = run_command("exit 1");
+        let output: CommandOutput = run_command("sh", "exit 1", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 1);
         assert_eq!(output.output, "");
 
-        let output = run_command("no-command");
+        let output: CommandOutput = run_command(
+            "sh",
+            "echo out; echo err >&2; exit 2",
+            &HashMap::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(output.output, "err\n");
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+
+        let output: CommandOutput =
+            run_command("sh", "printf 'a\\377\\n'", &HashMap::new(), None).unwrap();
+        assert_eq!(output.status, 0);
+        assert_eq!(output.output, "a\u{FFFD}\n");
+
+        let output = run_command("sh", "no-command", &HashMap::new(), None).unwrap();
         assert_eq!(output.status, 127);
-        assert!(output.output.contains("no-command: not found"));
+        assert!(output.output.contains("no-command"));
+        assert!(output.output.contains("not found"));
+    }
+
+    #[test]
+    fn test_shell_args() {
+        assert_eq!(shell_args("/bin/sh"), vec!["-c"]);
+        assert_eq!(shell_args("bash"), vec!["-c"]);
+        assert_eq!(shell_args("pwsh"), vec!["-NoLogo", "-Command"]);
+        assert_eq!(shell_args("powershell.exe"), vec!["-NoLogo", "-Command"]);
+        assert_eq!(shell_args("cmd.exe"), vec!["/C"]);
+    }
+
+    #[test]
+    fn test_run_command_missing_shell() {
+        let output = run_command(
+            "/no/such/shell",
+            "echo 'Hello World!'",
+            &HashMap::new(),
+            None,
+        );
+        assert!(
+            matches!(output, Err(SAError::CommandSpawn(shell, _)) if shell == "/no/such/shell")
+        );
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_run_argv_unix() {
+        let argv: Vec<String> = vec!["echo".to_string(), "it's $(not) a \"subshell\"".to_string()];
+        let output: CommandOutput = run_argv(&argv, &HashMap::new(), None).unwrap();
+        assert_eq!(output.status, 0);
+        assert_eq!(output.output, "it's $(not) a \"subshell\"\n");
+
+        let output = run_argv(&["no-command".to_string()], &HashMap::new(), None);
+        assert!(
+            matches!(output, Err(SAError::CommandSpawn(program, _)) if program == "no-command")
+        );
+
+        assert!(run_argv(&[], &HashMap::new(), None).is_err());
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_run_command_env_unix() {
+        let env: HashMap<String, String> =
+            HashMap::from([("SA_TEST_VALUE".to_string(), "from env".to_string())]);
+
+        let output: CommandOutput = run_command("sh", "echo $SA_TEST_VALUE", &env, None).unwrap();
+        assert_eq!(output.output, "from env\n");
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_run_command_timeout_unix() {
+        let start = std::time::Instant::now();
+        let output: CommandOutput = run_command(
+            "sh",
+            "sleep 10 & sleep 10",
+            &HashMap::new(),
+            Some(Duration::from_millis(100)),
+        )
+        .unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.status, TIMED_OUT_STATUS);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let output: CommandOutput = run_command(
+            "sh",
+            "echo done",
+            &HashMap::new(),
+            Some(Duration::from_secs(10)),
+        )
+        .unwrap();
+        assert!(!output.timed_out);
+        assert_eq!(output.output, "done\n");
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_run_command_timeout_ignores_term_unix() {
+        let start = std::time::Instant::now();
+        let output: CommandOutput = run_command(
+            "sh",
+            "sh -c 'trap \"\" TERM; sleep 30' & wait",
+            &HashMap::new(),
+            Some(Duration::from_millis(500)),
+        )
+        .unwrap();
+        assert!(output.timed_out);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let data = parse_output(r#"{"items": [{"name": "web"}]}"#, Parse::Json).unwrap();
+        assert_eq!(data, json!({"items": [{"name": "web"}]}));
+
+        let data = parse_output("name: web\nreplicas: 3\n", Parse::Yaml).unwrap();
+        assert_eq!(data, json!({"name": "web", "replicas": 3}));
+
+        let data = parse_output("one\ntwo\n", Parse::Lines).unwrap();
+        assert_eq!(data, json!(["one", "two"]));
+
+        let data =
+            parse_output("# comment\nHOME=/root\nname: web\n\nignored\n", Parse::Kv).unwrap();
+        assert_eq!(data, json!({"HOME": "/root", "name": "web"}));
+
+        assert!(parse_output("not json", Parse::Json).is_err());
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(format_duration(Duration::from_millis(1234)), "1.23s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m 12s");
+
+        let output = CommandOutput {
+            duration: Duration::from_millis(1500),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&output).unwrap();
+        assert_eq!(value["duration"], json!(1.5));
+        assert_eq!(
+            serde_json::from_value::<CommandOutput>(value).unwrap(),
+            output
+        );
     }
 }
@@ -1,7 +1,88 @@
+use std::path::PathBuf;
+
 #[derive(Debug)]
 pub enum SAError {
-    ShortcutFileRead(std::io::Error),
-    ShortcutFileParse(serde_yaml::Error),
+    ShortcutFileRead(PathBuf, std::io::Error),
+    ShortcutFileParse(PathBuf, serde_yaml::Error),
     CommandFailed(String),
     GlobFailure(glob::PatternError),
+    CommandSpawn(String, std::io::Error),
+    ShortcutInvalid(String),
+    OutputParse(String),
+    Prompt(std::io::Error),
+    Cancelled(String),
+    MissingArgument(String),
+    VariablesFile(String),
+    Inputs(String),
+    State(String),
+    History(String),
+    TemplateRender {
+        shortcut: String,
+        step: String,
+        message: String,
+    },
+}
+
+impl SAError {
+    // Apply `redact` to every message in the error, so secret values are never shown.
+    pub fn redact(self, redact: impl Fn(&str) -> String) -> SAError {
+        match self {
+            SAError::CommandFailed(message) => SAError::CommandFailed(redact(&message)),
+            SAError::CommandSpawn(program, err) => SAError::CommandSpawn(redact(&program), err),
+            SAError::ShortcutInvalid(message) => SAError::ShortcutInvalid(redact(&message)),
+            SAError::OutputParse(message) => SAError::OutputParse(redact(&message)),
+            SAError::Cancelled(message) => SAError::Cancelled(redact(&message)),
+            SAError::MissingArgument(message) => SAError::MissingArgument(redact(&message)),
+            SAError::VariablesFile(message) => SAError::VariablesFile(redact(&message)),
+            SAError::Inputs(message) => SAError::Inputs(redact(&message)),
+            SAError::State(message) => SAError::State(redact(&message)),
+            SAError::History(message) => SAError::History(redact(&message)),
+            SAError::TemplateRender {
+                shortcut,
+                step,
+                message,
+            } => SAError::TemplateRender {
+                shortcut,
+                step,
+                message: redact(&message),
+            },
+            error => error,
+        }
+    }
+}
+
+impl std::fmt::Display for SAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SAError::ShortcutFileRead(path, err) => {
+                write!(f, "Failed to read file '{}': {}", path.display(), err)
+            }
+            SAError::ShortcutFileParse(path, err) => {
+                write!(f, "Failed to parse YAML file '{}': {}", path.display(), err)
+            }
+            SAError::CommandFailed(err) => write!(f, "Failed to run command: {}", err),
+            SAError::GlobFailure(err) => write!(f, "Failed to parse files: {}", err),
+            SAError::CommandSpawn(program, err) => {
+                write!(f, "Failed to start '{}': {}", program, err)
+            }
+            SAError::ShortcutInvalid(err) => write!(f, "Invalid shortcut: {}", err),
+            SAError::OutputParse(err) => write!(f, "Failed to parse command output: {}", err),
+            SAError::Prompt(err) => write!(f, "Failed to read answer: {}", err),
+            SAError::Cancelled(err) => write!(f, "Cancelled: {}", err),
+            SAError::MissingArgument(err) => write!(f, "Missing argument: {}", err),
+            SAError::VariablesFile(err) => write!(f, "Failed to load variables: {}", err),
+            SAError::Inputs(err) => write!(f, "Failed to check inputs: {}", err),
+            SAError::State(err) => write!(f, "Failed to resume: {}", err),
+            SAError::History(err) => write!(f, "Failed to read history: {}", err),
+            SAError::TemplateRender {
+                shortcut,
+                step,
+                message,
+            } => write!(
+                f,
+                "Failed to render '{}' in shortcut '{}': {}",
+                step, shortcut, message
+            ),
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::commands::CommandOutput;
+use crate::history::RunStatus;
+
+// Something which happened during a run, printed as JSON with `--output json` or `jsonl`. Secret
+// values are hidden before an event is made.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    RunStarted {
+        shortcut: String,
+        args: HashMap<String, String>,
+        started: String,
+    },
+    StepStarted {
+        shortcut: String,
+        step: String,
+        name: String,
+        // The rendered command, or the quoted arguments of `argv` joined with spaces.
+        command: String,
+        dry_run: bool,
+    },
+    StepFinished {
+        shortcut: String,
+        step: String,
+        name: String,
+        skipped: bool,
+        result: CommandOutput,
+    },
+    RunFinished {
+        shortcut: String,
+        status: RunStatus,
+        #[serde(with = "crate::commands::seconds")]
+        duration: Duration,
+        error: Option<String>,
+    },
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        // Every field can be represented in JSON, so this can't fail.
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{Attempt, CommandOutput};
+    use crate::events::Event;
+    use crate::history::RunStatus;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_event_json() {
+        let event = Event::StepFinished {
+            shortcut: "deploy".to_string(),
+            step: "build".to_string(),
+            name: "Build".to_string(),
+            skipped: false,
+            result: CommandOutput {
+                output: "failed\n".to_string(),
+                stdout: "building\n".to_string(),
+                stderr: "failed\n".to_string(),
+                status: 1,
+                attempts: vec![Attempt::default()],
+                duration: Duration::from_millis(250),
+                ..Default::default()
+            },
+        };
+        let value: serde_json::Value = serde_json::from_str(&event.to_json()).unwrap();
+        assert_eq!(value["event"], json!("step_finished"));
+        assert_eq!(value["step"], json!("build"));
+        assert_eq!(value["result"]["output"], json!("failed\n"));
+        assert_eq!(value["result"]["stdout"], json!("building\n"));
+        assert_eq!(value["result"]["stderr"], json!("failed\n"));
+        assert_eq!(value["result"]["status"], json!(1));
+        assert_eq!(value["result"]["duration"], json!(0.25));
+
+        let event = Event::RunFinished {
+            shortcut: "deploy".to_string(),
+            status: RunStatus::Failed,
+            duration: Duration::from_secs(2),
+            error: Some("Failed to run command: Command 'Build' failed.".to_string()),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"run_finished","shortcut":"deploy","status":"failed","duration":2.0,"error":"Failed to run command: Command 'Build' failed."}"#
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::commands::format_duration;
+use crate::errors::SAError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Succeeded,
+    Failed,
+}
+
+// The outcome of a single command within a run.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub name: String,
+    pub status: i32,
+    pub duration_ms: u64,
+    // Whether the command was skipped, such as being up to date, rather than run.
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+impl StepRecord {
+    // A row of the summary printed after a run, with the name padded to `width`.
+    pub fn summary(&self, width: usize) -> String {
+        let (status, duration): (String, String) = match (self.skipped, self.status) {
+            (true, _) => (String::from("Skipped"), String::from("-")),
+            (false, 0) => (String::from("Succeeded"), self.duration()),
+            (false, status) => (format!("Failed ({})", status), self.duration()),
+        };
+
+        format!("{:<width$}  {:<12}  {:>8}", self.name, status, duration)
+    }
+
+    fn duration(&self) -> String {
+        format_duration(Duration::from_millis(self.duration_ms))
+    }
+}
+
+// A single run of a shortcut, as stored in the history file.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub shortcut: String,
+    // The arguments the shortcut was run with, apart from any secrets.
+    pub args: HashMap<String, String>,
+    pub cwd: String,
+    pub started: String,
+    pub finished: String,
+    pub status: RunStatus,
+    pub steps: Vec<StepRecord>,
+}
+
+impl HistoryEntry {
+    pub fn path(state_dir: &Path) -> PathBuf {
+        state_dir.join("history.jsonl")
+    }
+
+    // Add the entry to the end of the history file, as a single line.
+    pub fn append(&self, path: &Path) -> Result<(), SAError> {
+        let write_error =
+            |e: String| SAError::History(format!("Couldn't write '{}': {}", path.display(), e));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(e.to_string()))?;
+        };
+
+        let line: String = serde_json::to_string(self).map_err(|e| write_error(e.to_string()))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| write_error(e.to_string()))?;
+        writeln!(file, "{}", line).map_err(|e| write_error(e.to_string()))
+    }
+
+    // A line for the list of runs, starting with the entry's number.
+    pub fn summary(&self, number: usize) -> String {
+        let started: String = DateTime::parse_from_rfc3339(&self.started)
+            .map(|started| started.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| self.started.clone());
+        let status: &str = match self.status {
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+        };
+
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        args.sort();
+
+        format!(
+            "{:>4}  {}  {:<9}  {:>8}  {}  {}",
+            number,
+            started,
+            status,
+            format!("{:.1}s", self.duration_ms() as f64 / 1000.0),
+            self.shortcut,
+            args.join(" ")
+        )
+        .trim_end()
+        .to_string()
+    }
+
+    fn duration_ms(&self) -> i64 {
+        match (
+            DateTime::parse_from_rfc3339(&self.started),
+            DateTime::parse_from_rfc3339(&self.finished),
+        ) {
+            (Ok(started), Ok(finished)) => (finished - started).num_milliseconds(),
+            _ => 0,
+        }
+    }
+}
+
+// Read every run from the history file, numbered from 1 in the order they were run. Lines which
+// can't be parsed, such as from a run which was stopped while writing, are skipped.
+pub fn read_history(path: &Path) -> Result<Vec<(usize, HistoryEntry)>, SAError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    };
+
+    let content: String = fs::read_to_string(path)
+        .map_err(|e| SAError::History(format!("Couldn't read '{}': {}", path.display(), e)))?;
+
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            serde_json::from_str::<HistoryEntry>(line)
+                .ok()
+                .map(|entry| (index + 1, entry))
+        })
+        .collect())
+}
+
+// The most recent `limit` runs, only including those of a shortcut or status if given.
+pub fn filter_history(
+    entries: Vec<(usize, HistoryEntry)>,
+    shortcut: Option<&str>,
+    status: Option<RunStatus>,
+    limit: usize,
+) -> Vec<(usize, HistoryEntry)> {
+    let mut entries: Vec<(usize, HistoryEntry)> = entries
+        .into_iter()
+        .filter(|(_, entry)| shortcut.is_none_or(|shortcut| entry.shortcut == shortcut))
+        .filter(|(_, entry)| status.is_none_or(|status| entry.status == status))
+        .collect();
+
+    let skip: usize = entries.len().saturating_sub(limit);
+    entries.drain(..skip);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::{filter_history, read_history, HistoryEntry, RunStatus, StepRecord};
+    use std::collections::HashMap;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn entry(shortcut: &str, status: RunStatus) -> HistoryEntry {
+        HistoryEntry {
+            shortcut: shortcut.to_string(),
+            args: HashMap::from([("env".to_string(), "prod".to_string())]),
+            cwd: "/src".to_string(),
+            started: "2024-05-01T10:00:00+00:00".to_string(),
+            finished: "2024-05-01T10:00:02.500+00:00".to_string(),
+            status,
+            steps: vec![StepRecord {
+                name: "Build".to_string(),
+                status: 0,
+                duration_ms: 2500,
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let path = HistoryEntry::path(&tmp_dir.path().join("state"));
+        assert!(read_history(&path)
+            .expect("Couldn't read history.")
+            .is_empty());
+
+        entry("deploy", RunStatus::Succeeded)
+            .append(&path)
+            .expect("Couldn't write history.");
+        // A partly written line is skipped, without changing the numbers of the others.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("Couldn't open history.");
+        writeln!(file, "{{\"shortcut\": \"dep").expect("Couldn't write history.");
+        entry("build", RunStatus::Failed)
+            .append(&path)
+            .expect("Couldn't write history.");
+        entry("deploy", RunStatus::Failed)
+            .append(&path)
+            .expect("Couldn't write history.");
+
+        let history = read_history(&path).expect("Couldn't read history.");
+        let numbers: Vec<usize> = history.iter().map(|(number, _)| *number).collect();
+        assert_eq!(numbers, vec![1, 3, 4]);
+        assert_eq!(history[0].1, entry("deploy", RunStatus::Succeeded));
+
+        let filtered = filter_history(history.clone(), Some("deploy"), None, 20);
+        assert_eq!(filtered.len(), 2);
+        let filtered = filter_history(history.clone(), None, Some(RunStatus::Failed), 20);
+        assert_eq!(filtered[0].0, 3);
+        let filtered = filter_history(history, None, None, 1);
+        assert_eq!(filtered[0].0, 4);
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+
+    #[test]
+    fn test_history_summary() {
+        let summary = entry("deploy", RunStatus::Failed).summary(7);
+        assert!(summary.starts_with("   7  2024-05-01 10:00:00  failed"));
+        assert!(summary.ends_with("2.5s  deploy  env=prod"));
+    }
+
+    #[test]
+    fn test_step_summary() {
+        let record = StepRecord {
+            name: "Build".to_string(),
+            status: 0,
+            duration_ms: 1234,
+            ..Default::default()
+        };
+        assert_eq!(record.summary(6), "Build   Succeeded        1.23s");
+
+        let record = StepRecord {
+            status: 2,
+            ..record
+        };
+        assert_eq!(record.summary(5), "Build  Failed (2)       1.23s");
+
+        let record = StepRecord {
+            skipped: true,
+            ..record
+        };
+        assert_eq!(record.summary(5), "Build  Skipped              -");
+    }
+}
@@ -1,73 +1,214 @@
 use colored::Colorize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+mod cache;
 mod cli;
 mod commands;
 mod errors;
+mod events;
+mod history;
+mod prompt;
+mod runner;
 mod settings;
 mod shortcut;
-use cli::{build_cli, discover_commands, discover_config_dir};
-use commands::{run_command, CommandOutput};
+mod state;
+mod templating;
+use cache::Cache;
+use cli::{
+    build_cli, discover_cache_dir, discover_commands, discover_config_dir, discover_state_dir,
+    HISTORY,
+};
 use errors::SAError;
-use settings::Settings;
-use shortcut::{Shortcut, Variables};
-
-fn shortcut_alias() -> Result<(), SAError> {
-    let config_dir: String = discover_config_dir();
-    let shortcuts: HashMap<String, Shortcut> = discover_commands(config_dir)?;
-    let mut cli = build_cli(shortcuts.values().into_iter().collect());
-    let cli_matches = &cli.clone().get_matches();
-
-    let settings = Settings::new_from_matches(cli_matches);
-    settings.set_terminal_color();
+use history::{filter_history, read_history, HistoryEntry, RunStatus};
+use runner::Runner;
+use settings::{Output, Settings};
+use shortcut::Shortcut;
+use state::RunState;
+
+// Run a shortcut, saving its progress and recording it in the history. `prepare` can set up the
+// runner before it starts, such as to resume a previous run.
+fn run_shortcut(
+    settings: &Settings,
+    shortcuts: &HashMap<String, Shortcut>,
+    shortcut: &Shortcut,
+    config_dir: &str,
+    arg_matches: &clap::ArgMatches,
+    prepare: impl FnOnce(&mut Runner) -> Result<(), SAError>,
+) -> Result<(), SAError> {
+    let cache = Cache::new(&discover_cache_dir(config_dir));
+    let state_dir: PathBuf = discover_state_dir(config_dir);
+
+    let mut runner = Runner::new(
+        settings,
+        shortcuts,
+        shortcut,
+        &Path::new(config_dir).join("templates"),
+        &cache,
+        arg_matches,
+    );
+    runner.keep_state(&RunState::path(&state_dir, &shortcut.name));
+    runner.keep_history(&HistoryEntry::path(&state_dir));
+    prepare(&mut runner)?;
+
+    let result = runner.run();
+    runner.print_summary();
+    runner.print_events();
+    result
+}
 
-    if let Some((cmd_name, arg_matches)) = cli_matches.subcommand() {
-        let shortcut = &shortcuts[cmd_name];
+// Run a shortcut again with the arguments of the last run, carrying on from the step which failed.
+fn resume(
+    settings: &Settings,
+    shortcuts: &HashMap<String, Shortcut>,
+    config_dir: &str,
+    name: &str,
+) -> Result<(), SAError> {
+    let shortcut: &Shortcut = shortcuts
+        .get(name)
+        .ok_or_else(|| SAError::State(format!("'{}' isn't a shortcut.", name)))?;
+
+    let state: RunState =
+        match RunState::load(&RunState::path(&discover_state_dir(config_dir), name))? {
+            Some(state) if !state.finished(shortcut) => state,
+            Some(_) => {
+                return Err(SAError::State(format!(
+                    "The last run of '{}' succeeded, so there is nothing to resume.",
+                    name
+                )))
+            }
+            None => {
+                return Err(SAError::State(format!(
+                    "'{}' hasn't been run yet, so there is nothing to resume.",
+                    name
+                )))
+            }
+        };
+
+    // Run with the same arguments as last time, apart from secrets which weren't saved.
+    let arg_matches = shortcut.matches_from(&state.args).map_err(|e| {
+        SAError::State(format!("Couldn't use the arguments of the last run: {}", e))
+    })?;
+
+    run_shortcut(
+        settings,
+        shortcuts,
+        shortcut,
+        config_dir,
+        &arg_matches,
+        |runner| {
+            runner.restore(state);
+            Ok(())
+        },
+    )
+}
 
-        let mut vars = Variables::new(shortcut, arg_matches);
+// List previous runs, or run one of them again with the same arguments.
+fn history(
+    settings: &Settings,
+    shortcuts: &HashMap<String, Shortcut>,
+    config_dir: &str,
+    history_matches: &clap::ArgMatches,
+) -> Result<(), SAError> {
+    let entries = read_history(&HistoryEntry::path(&discover_state_dir(config_dir)))?;
+
+    if let Some(number) = history_matches.get_one::<usize>("rerun") {
+        let entry: &HistoryEntry = entries
+            .iter()
+            .find(|(entry_number, _)| entry_number == number)
+            .map(|(_, entry)| entry)
+            .ok_or_else(|| SAError::History(format!("There is no run numbered {}.", number)))?;
+        let shortcut: &Shortcut = shortcuts.get(&entry.shortcut).ok_or_else(|| {
+            SAError::History(format!("'{}' isn't a shortcut anymore.", entry.shortcut))
+        })?;
+        let arg_matches = shortcut.matches_from(&entry.args).map_err(|e| {
+            SAError::History(format!(
+                "Couldn't use the arguments of run {}: {}",
+                number, e
+            ))
+        })?;
+
+        return run_shortcut(
+            settings,
+            shortcuts,
+            shortcut,
+            config_dir,
+            &arg_matches,
+            |_| Ok(()),
+        );
+    };
 
-        let mut first: bool = true;
+    let status: Option<RunStatus> =
+        history_matches
+            .get_one::<String>("status")
+            .map(|status| match status.as_str() {
+                "failed" => RunStatus::Failed,
+                _ => RunStatus::Succeeded,
+            });
+    let entries = filter_history(
+        entries,
+        history_matches
+            .get_one::<String>("shortcut")
+            .map(String::as_str),
+        status,
+        *history_matches.get_one::<usize>("limit").unwrap_or(&20),
+    );
+
+    for (number, entry) in entries.iter() {
+        match entry.status {
+            RunStatus::Succeeded => println!("{}", entry.summary(*number)),
+            RunStatus::Failed => println!("{}", entry.summary(*number).red()),
+        };
+    }
 
-        for cmd in shortcut.commands.iter() {
-            if settings.show_header {
-                let mut header = format!("{:=<80}", format!("[SA] Running '{}' ", &cmd.name));
+    Ok(())
+}
 
-                if let Some(desc) = &cmd.description {
-                    header = format!("{header}\n{desc}\n{:=<80}", String::new());
-                };
+fn shortcut_alias(output: &mut Output) -> Result<(), SAError> {
+    let config_dir: String = discover_config_dir();
+    let shortcuts: HashMap<String, Shortcut> = discover_commands(config_dir.clone())?;
+    let mut cli = build_cli(shortcuts.values().collect());
+    let cli_matches = &cli.clone().get_matches();
 
-                if first {
-                    println!("{}", header.green());
-                    first = false;
-                } else {
-                    println!("\n{}", header.green());
+    let mut settings = Settings::new_from_file(&Path::new(&config_dir).join(".settings.yaml"))?;
+    settings.update_from_matches(cli_matches);
+    settings.set_terminal_color();
+    *output = settings.output;
+
+    if let Some(name) = cli_matches.get_one::<String>("resume") {
+        if cli_matches.subcommand().is_some() {
+            return Err(SAError::State(String::from(
+                "--resume is given the shortcut to resume, so no other shortcut can be given.",
+            )));
+        };
+
+        resume(&settings, &shortcuts, &config_dir, name)?;
+    } else if let Some((cmd_name, arg_matches)) = cli_matches.subcommand() {
+        // Shortcuts take priority over built in commands with the same name.
+        if cmd_name == HISTORY && !shortcuts.contains_key(cmd_name) {
+            return history(&settings, &shortcuts, &config_dir, arg_matches);
+        };
+
+        let from: Option<&String> = cli_matches.get_one::<String>("from");
+        let only: Option<&String> = cli_matches.get_one::<String>("only");
+        let state_file: PathBuf = RunState::path(&discover_state_dir(&config_dir), cmd_name);
+
+        run_shortcut(
+            &settings,
+            &shortcuts,
+            &shortcuts[cmd_name],
+            &config_dir,
+            arg_matches,
+            |runner| {
+                if from.is_some() || only.is_some() {
+                    if let Some(state) = RunState::load(&state_file)? {
+                        runner.restore(state);
+                    };
+                    runner.select(from.map(String::as_str), only.map(String::as_str))?;
                 };
-            };
-
-            let command: String = vars.render_command(&cmd.command);
-            let result: CommandOutput = run_command(&command);
-
-            if settings.show_body {
-                print!("{}", &result.output);
-            };
-
-            if settings.show_footer {
-                let footer: String = format!(
-                    "{:=<80}",
-                    format!("[SA] Exit Code: {} ", result.status.clone())
-                );
-                println!("{}", footer.green());
-            };
-
-            if result.status != 0 {
-                return Err(SAError::CommandFailed(format!(
-                    "Command '{}' failed.",
-                    cmd.name
-                )));
-            }
-
-            vars.add_command(cmd.name.to_owned(), result)
-        }
+                Ok(())
+            },
+        )?;
     } else {
         cli.print_help().unwrap();
     };
@@ -76,25 +217,15 @@ fn shortcut_alias() -> Result<(), SAError> {
 }
 
 fn main() {
-    let run_program = shortcut_alias();
+    let mut output: Output = Output::Text;
+    let run_program = shortcut_alias(&mut output);
 
+    // With structured output, errors are kept apart from the events so they can still be parsed.
     if let Err(error) = run_program {
-        match error {
-            SAError::ShortcutFileRead(err) => {
-                println!("{}", format!("[SA] Failed to read file: {}", err).red());
-            }
-            SAError::ShortcutFileParse(err) => {
-                println!(
-                    "{}",
-                    format!("[SA] Failed to parse YAML file: {}", err).red()
-                );
-            }
-            SAError::CommandFailed(err) => {
-                println!("{}", format!("[SA] Failed to run command: {}", err).red());
-            }
-            SAError::GlobFailure(err) => {
-                println!("{}", format!("[SA] Failed to parse files: {}", err).red());
-            }
-        }
+        match output {
+            Output::Text => println!("{}", format!("[SA] {}", error).red()),
+            Output::Json | Output::Jsonl => eprintln!("[SA] {}", error),
+        };
+        std::process::exit(1);
     };
 }
@@ -0,0 +1,171 @@
+use std::io::{self, BufRead, ErrorKind, IsTerminal, Write};
+
+use crate::errors::SAError;
+
+// Whether the user can be asked questions on the terminal.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+fn read_answer(input: &mut impl BufRead) -> Result<String, SAError> {
+    let mut answer = String::new();
+
+    match input.read_line(&mut answer) {
+        Ok(_) => Ok(answer.trim().to_string()),
+        Err(e) => Err(SAError::Prompt(e)),
+    }
+}
+
+fn write_question(output: &mut impl Write, question: &str) -> Result<(), SAError> {
+    write!(output, "{}", question)
+        .and_then(|_| output.flush())
+        .map_err(SAError::Prompt)
+}
+
+// Ask a yes or no question. Anything other than yes is treated as no.
+pub fn confirm_from(
+    question: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<bool, SAError> {
+    write_question(output, &format!("{} [y/N] ", question))?;
+
+    let answer: String = read_answer(input)?.to_lowercase();
+
+    Ok(answer == "y" || answer == "yes")
+}
+
+pub fn confirm(question: &str) -> Result<bool, SAError> {
+    confirm_from(question, &mut io::stdin().lock(), &mut io::stdout())
+}
+
+pub fn ask_from(
+    question: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<String, SAError> {
+    write_question(output, &format!("{}: ", question))?;
+    read_answer(input)
+}
+
+pub fn ask(question: &str) -> Result<String, SAError> {
+    ask_from(question, &mut io::stdin().lock(), &mut io::stdout())
+}
+
+// Ask for a value without showing what is typed.
+pub fn ask_secret(question: &str) -> Result<String, SAError> {
+    rpassword::prompt_password(format!("{}: ", question)).map_err(SAError::Prompt)
+}
+
+// Show a numbered list of choices, asking until either a number or one of the choices is given.
+pub fn choose_from(
+    question: &str,
+    choices: &[String],
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<String, SAError> {
+    let mut list: String = format!("{}:\n", question);
+    for (index, choice) in choices.iter().enumerate() {
+        list.push_str(&format!("  {}) {}\n", index + 1, choice));
+    }
+    write_question(output, &list)?;
+
+    loop {
+        write_question(output, "> ")?;
+
+        let mut answer = String::new();
+        match input.read_line(&mut answer) {
+            Ok(0) => {
+                return Err(SAError::Prompt(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "No choice was made.",
+                )))
+            }
+            Ok(_) => (),
+            Err(e) => return Err(SAError::Prompt(e)),
+        };
+        let answer: &str = answer.trim();
+
+        if let Some(choice) = choices.iter().find(|choice| choice.as_str() == answer) {
+            return Ok(choice.clone());
+        };
+
+        if let Ok(number) = answer.parse::<usize>() {
+            if number >= 1 && number <= choices.len() {
+                return Ok(choices[number - 1].clone());
+            };
+        };
+    }
+}
+
+pub fn choose(question: &str, choices: &[String]) -> Result<String, SAError> {
+    choose_from(
+        question,
+        choices,
+        &mut io::stdin().lock(),
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prompt::{ask_from, choose_from, confirm_from};
+
+    #[test]
+    fn test_confirm_from() {
+        let mut output: Vec<u8> = Vec::new();
+
+        let answer = confirm_from("Drop the database?", &mut "y\n".as_bytes(), &mut output);
+        assert!(answer.unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Drop the database? [y/N] "
+        );
+
+        let answer = confirm_from("Drop?", &mut "YES\n".as_bytes(), &mut Vec::new());
+        assert!(answer.unwrap());
+
+        let answer = confirm_from("Drop?", &mut "n\n".as_bytes(), &mut Vec::new());
+        assert!(!answer.unwrap());
+
+        let answer = confirm_from("Drop?", &mut "\n".as_bytes(), &mut Vec::new());
+        assert!(!answer.unwrap());
+
+        let answer = confirm_from("Drop?", &mut "".as_bytes(), &mut Vec::new());
+        assert!(!answer.unwrap());
+    }
+
+    #[test]
+    fn test_ask_from() {
+        let mut output: Vec<u8> = Vec::new();
+
+        let answer = ask_from("Branch", &mut "main \n".as_bytes(), &mut output);
+        assert_eq!(answer.unwrap(), "main");
+        assert_eq!(String::from_utf8(output).unwrap(), "Branch: ");
+    }
+
+    #[test]
+    fn test_choose_from() {
+        let choices: Vec<String> = vec!["dev".to_string(), "prod".to_string()];
+        let mut output: Vec<u8> = Vec::new();
+
+        let answer = choose_from("Environment", &choices, &mut "2\n".as_bytes(), &mut output);
+        assert_eq!(answer.unwrap(), "prod");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Environment:\n  1) dev\n  2) prod\n> "
+        );
+
+        // Invalid answers are asked again.
+        let answer = choose_from(
+            "Environment",
+            &choices,
+            &mut "3\nstaging\ndev\n".as_bytes(),
+            &mut Vec::new(),
+        );
+        assert_eq!(answer.unwrap(), "dev");
+
+        let answer = choose_from("Environment", &choices, &mut "".as_bytes(), &mut Vec::new());
+        assert!(answer.is_err());
+    }
+}
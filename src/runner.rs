@@ -0,0 +1,1615 @@
+use chrono::{DateTime, Local};
+use colored::Colorize;
+use minijinja::Environment;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cache::{find_files, hash_inputs, outputs_exist, outputs_newer, Cache};
+use crate::commands::{
+    format_duration, parse_output, run_argv, run_command, Attempt, CommandOutput,
+};
+use crate::errors::SAError;
+use crate::events::Event;
+use crate::history::{HistoryEntry, RunStatus, StepRecord};
+use crate::prompt::{ask, ask_secret, choose, confirm, is_interactive};
+use crate::settings::{Output, Settings};
+use crate::shortcut::{
+    parse_items, regex_capture, Command, IncludeFile, LoopItem, SetValue, Shortcut, Variables,
+};
+use crate::state::RunState;
+use crate::templating::{environment, shell_quote};
+
+// The header title for an attempt, only showing the attempt number when there can be more than one.
+fn attempt_title(title: &str, attempt: u32, cmd: &Command) -> String {
+    if cmd.attempts() > 1 {
+        format!("{} (Attempt {}/{})", title, attempt, cmd.attempts())
+    } else {
+        title.to_string()
+    }
+}
+
+// A command with all of its templates rendered, ready to run.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RenderedCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+// Where a command is up to, when running the commands as a graph.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum StepState {
+    Waiting,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+// The hash of a command's inputs, and whether the command can be skipped.
+struct Fingerprint {
+    hash: String,
+    up_to_date: bool,
+}
+
+// A rendered command, with everything needed to execute it away from the runner.
+struct Job<'c> {
+    cmd: &'c Command,
+    rendered: RenderedCommand,
+    timeout: Option<Duration>,
+    fingerprint: Option<Fingerprint>,
+}
+
+impl Job<'_> {
+    fn up_to_date(&self) -> bool {
+        self.fingerprint
+            .as_ref()
+            .is_some_and(|fingerprint| fingerprint.up_to_date)
+    }
+}
+
+// Execute a job, retrying it as configured. `on_attempt` is given the result of each attempt,
+// and whether it will be retried. Each result holds every attempt so far, and the final
+// result's duration covers all of them.
+fn execute(
+    shell: &str,
+    env: &HashMap<String, String>,
+    job: &Job,
+    mut on_attempt: impl FnMut(&CommandOutput, bool),
+) -> Result<CommandOutput, SAError> {
+    let mut attempt: u32 = 1;
+    let mut attempts: Vec<Attempt> = Vec::new();
+    let started: Instant = Instant::now();
+
+    loop {
+        let attempt_started: Instant = Instant::now();
+        let mut result: CommandOutput = match &job.rendered {
+            RenderedCommand::Argv(argv) => run_argv(argv, env, job.timeout)?,
+            RenderedCommand::Shell(command) => run_command(shell, command, env, job.timeout)?,
+        };
+        result.duration = attempt_started.elapsed();
+        attempts.push(Attempt::from(&result));
+        result.attempts = attempts.clone();
+
+        match &job.cmd.retry {
+            Some(retry) if attempt < retry.attempts && retry.should_retry(result.status) => {
+                on_attempt(&result, true);
+                thread::sleep(retry.delay_before(attempt + 1));
+                attempt += 1;
+            }
+            _ => {
+                result.duration = started.elapsed();
+                on_attempt(&result, false);
+                return Ok(result);
+            }
+        };
+    }
+}
+
+// Execute jobs on up to `max_jobs` threads at once. As each job finishes, `on_finish` is given
+// its index, the result of every attempt, and the final result.
+fn execute_parallel(
+    shell: &str,
+    env: &HashMap<String, String>,
+    jobs: &[Job],
+    max_jobs: usize,
+    mut on_finish: impl FnMut(usize, Vec<CommandOutput>, Result<CommandOutput, SAError>),
+) {
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..max_jobs.clamp(1, jobs.len().max(1)) {
+            let sender = sender.clone();
+            let next: &AtomicUsize = &next;
+
+            scope.spawn(move || loop {
+                let index: usize = next.fetch_add(1, Ordering::SeqCst);
+                let job: &Job = match jobs.get(index) {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                let mut attempts: Vec<CommandOutput> = Vec::new();
+                let result = execute(shell, env, job, |result, _| attempts.push(result.clone()));
+
+                if sender.send((index, attempts, result)).is_err() {
+                    break;
+                };
+            });
+        }
+        drop(sender);
+
+        for (index, attempts, result) in receiver {
+            on_finish(index, attempts, result);
+        }
+    });
+}
+
+pub struct Runner<'a> {
+    settings: &'a Settings,
+    // Every shortcut, so commands can run other shortcuts.
+    shortcuts: &'a HashMap<String, Shortcut>,
+    shortcut: &'a Shortcut,
+    template_dir: PathBuf,
+    cache: &'a Cache,
+    env: Environment<'static>,
+    pub vars: Variables,
+    // Values from `env_files`, passed to every command.
+    process_env: HashMap<String, String>,
+    first: Cell<bool>,
+    // Where to save the progress of the run, if anywhere.
+    state_file: Option<PathBuf>,
+    // The keys of the steps which have completed, or are to be treated as completed.
+    completed: Vec<String>,
+    // The keys of the steps whose results weren't saved by the last run, for holding a secret.
+    secret: Vec<String>,
+    // Where to record the run once it finishes, if anywhere.
+    history_file: Option<PathBuf>,
+    // The outcome of each command which has been run or skipped.
+    records: Vec<StepRecord>,
+    // How long the last run took.
+    elapsed: Duration,
+    // The events of the run, kept to be printed together with `--output json`.
+    events: Vec<Event>,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(
+        settings: &'a Settings,
+        shortcuts: &'a HashMap<String, Shortcut>,
+        shortcut: &'a Shortcut,
+        template_dir: &Path,
+        cache: &'a Cache,
+        cli_matches: &clap::ArgMatches,
+    ) -> Runner<'a> {
+        Runner {
+            settings,
+            shortcuts,
+            shortcut,
+            template_dir: template_dir.to_path_buf(),
+            cache,
+            env: environment(shortcut, template_dir),
+            vars: Variables::new(shortcut, cli_matches),
+            process_env: HashMap::new(),
+            first: Cell::new(true),
+            state_file: None,
+            completed: Vec::new(),
+            secret: Vec::new(),
+            history_file: None,
+            records: Vec::new(),
+            elapsed: Duration::ZERO,
+            events: Vec::new(),
+        }
+    }
+
+    // Save the progress of the run to a file after each step, so it can be resumed.
+    pub fn keep_state(&mut self, path: &Path) {
+        self.state_file = Some(path.to_path_buf());
+    }
+
+    // Add the run to the history file once it finishes.
+    pub fn keep_history(&mut self, path: &Path) {
+        self.history_file = Some(path.to_path_buf());
+    }
+
+    // Carry on from a previous run, reusing the results of the steps which completed.
+    pub fn restore(&mut self, state: RunState) {
+        self.vars.variables.extend(state.variables);
+        self.vars.commands.extend(state.commands);
+        self.completed = state.completed;
+        self.secret = state.secret;
+    }
+
+    // Only run the steps from `from` onwards, or only the step `only`. The others are treated as
+    // completed, so any results restored for them can be used.
+    pub fn select(&mut self, from: Option<&str>, only: Option<&str>) -> Result<(), SAError> {
+        let steps: Vec<&Command> = self.shortcut.steps();
+        let position = |step: &str| {
+            steps
+                .iter()
+                .position(|cmd| cmd.key() == step || cmd.name == step)
+                .ok_or_else(|| {
+                    SAError::State(format!(
+                        "Shortcut '{}' has no step '{}'.",
+                        self.shortcut.name, step
+                    ))
+                })
+        };
+
+        let selected: Vec<usize> = match (from, only) {
+            (_, Some(step)) => vec![position(step)?],
+            (Some(step), None) => (position(step)?..steps.len()).collect(),
+            (None, None) => return Ok(()),
+        };
+
+        // Steps with a secret in their result have to run again, so they can't be skipped.
+        if let Some((_, cmd)) = steps
+            .iter()
+            .enumerate()
+            .find(|(index, cmd)| !selected.contains(index) && self.secret.contains(&cmd.key()))
+        {
+            return Err(SAError::State(format!(
+                "The result of step '{}' held a secret, so it wasn't saved. Use --from '{}' to run it again.",
+                cmd.name, cmd.name
+            )));
+        };
+
+        self.completed = steps
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !selected.contains(index))
+            .map(|(_, cmd)| cmd.key())
+            .collect();
+        Ok(())
+    }
+
+    // Save the steps which have completed and their results, leaving out anything secret. A state
+    // file which can't be written only means the run can't be resumed.
+    fn save_state(&self, failed: Option<&str>) {
+        let path: &Path = match &self.state_file {
+            Some(path) if !self.settings.dry_run => path,
+            _ => return,
+        };
+        // Results holding a secret can't be saved as they are, and reusing them with the secret
+        // hidden would pass `****` to later steps. They are left out, and their steps run again.
+        let commands: HashMap<String, CommandOutput> = self
+            .vars
+            .commands
+            .iter()
+            .filter(|(_, output)| self.vars.redact_output(output) == **output)
+            .map(|(key, output)| (key.clone(), output.clone()))
+            .collect();
+        let variables: HashMap<String, String> = self.vars.without_secrets(&self.vars.variables);
+        let (secret, completed): (Vec<String>, Vec<String>) =
+            self.completed.iter().cloned().partition(|key| {
+                self.shortcut
+                    .steps()
+                    .into_iter()
+                    .find(|cmd| cmd.key() == *key)
+                    .is_some_and(|cmd| self.holds_secret(cmd, &commands, &variables))
+            });
+
+        let state: RunState = RunState {
+            shortcut: self.shortcut.name.clone(),
+            args: self.vars.without_secrets(&self.vars.args),
+            variables,
+            commands,
+            completed,
+            failed: failed.map(String::from),
+            secret,
+        };
+
+        let _ = state.save(path);
+    }
+
+    // Whether any result or variable of a step was left out of the state for holding a secret.
+    // The results of loops are stored under the step's key followed by an index.
+    fn holds_secret(
+        &self,
+        cmd: &Command,
+        commands: &HashMap<String, CommandOutput>,
+        variables: &HashMap<String, String>,
+    ) -> bool {
+        let mut cmds: Vec<&Command> = vec![cmd];
+        cmds.extend(cmd.parallel.iter().flatten());
+
+        cmds.iter().any(|cmd| {
+            let key: String = cmd.key();
+            let prefix: String = format!("{}_", key);
+
+            self.vars
+                .commands
+                .keys()
+                .filter(|saved| **saved == key || saved.starts_with(&prefix))
+                .any(|saved| !commands.contains_key(saved))
+                || cmd.set.iter().flatten().any(|(name, _)| {
+                    self.vars.variables.contains_key(name) && !variables.contains_key(name)
+                })
+        })
+    }
+
+    // Save the state of a graph, with the steps which have succeeded so far as completed.
+    fn save_graph_state(&mut self, keys: &[String], states: &[StepState], failed: Option<&str>) {
+        self.completed = keys
+            .iter()
+            .zip(states.iter())
+            .filter(|(_, state)| **state == StepState::Succeeded)
+            .map(|(key, _)| key.clone())
+            .collect();
+        self.save_state(failed);
+    }
+
+    fn print_completed(&mut self, cmd: &Command) {
+        if self.settings.show_header {
+            self.print_header(String::from("Already Completed"), cmd);
+        };
+        self.skip_record(cmd);
+    }
+
+    fn skip_record(&mut self, cmd: &Command) {
+        self.records.push(StepRecord {
+            name: self.vars.redact(&cmd.name),
+            skipped: true,
+            ..Default::default()
+        });
+        self.emit(Event::StepFinished {
+            shortcut: self.shortcut.name.clone(),
+            step: cmd.key(),
+            name: self.vars.redact(&cmd.name),
+            skipped: true,
+            result: CommandOutput::default(),
+        });
+    }
+
+    // Print the event straight away with `--output jsonl`, or keep it for `--output json`.
+    fn emit(&mut self, event: Event) {
+        match self.settings.output {
+            Output::Text => {}
+            Output::Json => self.events.push(event),
+            Output::Jsonl => println!("{}", event.to_json()),
+        };
+    }
+
+    fn emit_started(&mut self, cmd: &Command, rendered: &RenderedCommand) {
+        let command: String = match rendered {
+            RenderedCommand::Shell(command) => command.clone(),
+            RenderedCommand::Argv(argv) => argv
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<String>>()
+                .join(" "),
+        };
+
+        self.emit(Event::StepStarted {
+            shortcut: self.shortcut.name.clone(),
+            step: cmd.key(),
+            name: self.vars.redact(&cmd.name),
+            command: self.vars.redact(&command),
+            dry_run: self.settings.dry_run,
+        });
+    }
+
+    // Print every event of the run as a single JSON array, for `--output json`.
+    pub fn print_events(&self) {
+        if self.settings.output == Output::Json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&self.events).unwrap_or_default()
+            );
+        };
+    }
+
+    // Print the name, status and duration of every step, followed by the total time taken.
+    pub fn print_summary(&self) {
+        if !self.settings.show_summary || self.settings.dry_run || self.records.is_empty() {
+            return;
+        };
+
+        let width: usize = self
+            .records
+            .iter()
+            .map(|record| record.name.chars().count())
+            .max()
+            .unwrap_or_default()
+            .max(5);
+
+        println!("\n{}", format!("{:=<80}", "[SA] Summary ").green());
+        for record in self.records.iter() {
+            let line: String = record.summary(width);
+            match record.status {
+                0 => println!("{}", line),
+                _ => println!("{}", line.red()),
+            };
+        }
+        println!(
+            "{}",
+            format!(
+                "{:<width$}  {:<12}  {:>8}",
+                "Total",
+                "",
+                format_duration(self.elapsed)
+            )
+            .bold()
+        );
+    }
+
+    // Record the run, leaving out anything secret. A history file which can't be written only
+    // means the run is missing from it.
+    fn save_history(&self, started: DateTime<Local>, status: RunStatus) {
+        let path: &Path = match &self.history_file {
+            Some(path) if !self.settings.dry_run => path,
+            _ => return,
+        };
+
+        let entry: HistoryEntry = HistoryEntry {
+            shortcut: self.shortcut.name.clone(),
+            args: self.vars.without_secrets(&self.vars.args),
+            cwd: env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            started: started.to_rfc3339(),
+            finished: Local::now().to_rfc3339(),
+            status,
+            steps: self.records.clone(),
+        };
+
+        let _ = entry.append(path);
+    }
+
+    // Run the shortcut, hiding any secret values in the error.
+    pub fn run(&mut self) -> Result<(), SAError> {
+        let started: DateTime<Local> = Local::now();
+        let timer: Instant = Instant::now();
+        self.emit(Event::RunStarted {
+            shortcut: self.shortcut.name.clone(),
+            args: self.vars.without_secrets(&self.vars.args),
+            started: started.to_rfc3339(),
+        });
+
+        let result = self
+            .run_commands()
+            .map_err(|error| error.redact(|text| self.vars.redact(text)));
+        self.elapsed = timer.elapsed();
+
+        let status: RunStatus = match result {
+            Ok(()) => RunStatus::Succeeded,
+            Err(_) => RunStatus::Failed,
+        };
+        self.save_history(started, status);
+        self.emit(Event::RunFinished {
+            shortcut: self.shortcut.name.clone(),
+            status,
+            duration: self.elapsed,
+            error: result.as_ref().err().map(|error| error.to_string()),
+        });
+        result
+    }
+
+    fn run_commands(&mut self) -> Result<(), SAError> {
+        self.prompt_arguments()?;
+        self.load_files()?;
+
+        if let Some(question) = &self.shortcut.confirm {
+            if !self.settings.dry_run {
+                let question: String = self
+                    .vars
+                    .render_text(&self.env, question)
+                    .map_err(|e| self.render_error(&self.shortcut.name, e))?;
+                self.confirm(&question, &self.shortcut.name)?;
+            };
+        };
+
+        if self.shortcut.uses_needs() {
+            return self.run_graph();
+        };
+
+        for cmd in self.shortcut.commands.iter() {
+            if self.completed.contains(&cmd.key()) {
+                self.print_completed(cmd);
+                continue;
+            };
+
+            if let Err(error) = self.run_step(cmd) {
+                self.save_state(Some(&cmd.name));
+                return Err(error);
+            };
+            self.completed.push(cmd.key());
+            self.save_state(None);
+        }
+
+        Ok(())
+    }
+
+    // Run a command of any kind, waiting for it to finish.
+    fn run_step(&mut self, cmd: &Command) -> Result<(), SAError> {
+        if cmd.is_loop() {
+            return self.run_loop(cmd);
+        };
+
+        match (&cmd.parallel, &cmd.run) {
+            (_, Some(name)) => self.run_shortcut(cmd, name),
+            (Some(members), None) if self.settings.dry_run => self.dry_run_parallel(cmd, members),
+            (Some(members), None) => self.run_parallel(cmd, members),
+            (None, None) if self.settings.dry_run => self.dry_run_command(cmd),
+            (None, None) => self.run_command(cmd),
+        }
+    }
+
+    // Run a copy of the command for each item of `for_each` or `matrix`, one at a time, or in
+    // parallel when `max_jobs` is set.
+    fn run_loop(&mut self, cmd: &Command) -> Result<(), SAError> {
+        let items: Vec<LoopItem> = match &cmd.for_each {
+            Some(for_each) => {
+                self.vars.item = None;
+                parse_items(&self.render_text(cmd, for_each)?)
+                    .into_iter()
+                    .map(LoopItem::Item)
+                    .collect()
+            }
+            None => cmd.matrix_items(),
+        };
+        let steps: Vec<Command> = cmd.expand(items);
+        // Each copy asks its own confirmation, so the group doesn't ask too.
+        let group: Command = Command {
+            confirm: None,
+            ..cmd.clone()
+        };
+
+        match cmd.max_jobs {
+            Some(_) if self.settings.dry_run => self.dry_run_parallel(&group, &steps),
+            Some(_) => self.run_parallel(&group, &steps),
+            None => {
+                for step in steps.iter() {
+                    self.run_step(step)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn render_error(&self, step: &str, error: minijinja::Error) -> SAError {
+        SAError::TemplateRender {
+            shortcut: self.shortcut.name.clone(),
+            step: step.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    fn render_text(&self, cmd: &Command, text: &str) -> Result<String, SAError> {
+        self.vars
+            .render_text(&self.env, text)
+            .map_err(|e| self.render_error(&cmd.name, e))
+    }
+
+    // Ask for any arguments which weren't given on the command line, if they allow it.
+    fn prompt_arguments(&mut self) -> Result<(), SAError> {
+        let arguments = match &self.shortcut.args {
+            Some(arguments) => arguments,
+            None => return Ok(()),
+        };
+
+        for arg in arguments.iter() {
+            if self.vars.args.contains_key(&arg.name) {
+                continue;
+            };
+
+            let question: String = match arg.prompt_question() {
+                Some(question) => question,
+                None => continue,
+            };
+
+            if !self.interactive() {
+                return Err(SAError::MissingArgument(format!(
+                    "--{} is required, and there is no terminal to ask for it on.",
+                    arg.name
+                )));
+            };
+
+            let value: String = if let Some(choices) = &arg.choices {
+                choose(&question, choices)?
+            } else if arg.secret.unwrap_or(false) {
+                ask_secret(&question)?
+            } else {
+                ask(&question)?
+            };
+
+            if arg.secret.unwrap_or(false) {
+                self.vars.add_secret(&value);
+            };
+            self.vars.args.insert(arg.name.clone(), value);
+        }
+
+        Ok(())
+    }
+
+    // Load `env_files` and then `variables_from`, relative to the shortcut file.
+    fn load_files(&mut self) -> Result<(), SAError> {
+        for file in self.shortcut.env_files.iter().flatten() {
+            if let Some(path) = self.include_path(file)? {
+                let values: HashMap<String, String> = self.vars.add_env_file(&path)?;
+                self.process_env.extend(values);
+            };
+        }
+
+        for file in self.shortcut.variables_from.iter().flatten() {
+            if let Some(path) = self.include_path(file)? {
+                self.vars.add_variables_file(&path)?;
+            };
+        }
+
+        Ok(())
+    }
+
+    // Render the path of a file to load, or None if it is optional and missing.
+    fn include_path(&self, file: &IncludeFile) -> Result<Option<PathBuf>, SAError> {
+        let path: String = self
+            .vars
+            .render_text(&self.env, file.path())
+            .map_err(|e| self.render_error(&self.shortcut.name, e))?;
+        let path: PathBuf = self.shortcut.dir().join(path);
+
+        if path.exists() {
+            Ok(Some(path))
+        } else if file.optional() {
+            Ok(None)
+        } else {
+            Err(SAError::VariablesFile(format!(
+                "'{}' does not exist.",
+                path.display()
+            )))
+        }
+    }
+
+    // Ask before running. Without a terminal to ask on, only --yes allows it to run.
+    // Questions are only asked on a terminal, and never with structured output, as they would be
+    // mixed in with it.
+    fn interactive(&self) -> bool {
+        self.settings.output == Output::Text && is_interactive()
+    }
+
+    fn confirm(&self, question: &str, name: &str) -> Result<(), SAError> {
+        let confirmed: bool = if self.settings.assume_yes {
+            true
+        } else if self.interactive() {
+            confirm(&self.vars.redact(question))?
+        } else {
+            return Err(SAError::Cancelled(format!(
+                "'{}' needs confirmation, but there is no terminal to ask on. Use --yes to confirm.",
+                name
+            )));
+        };
+
+        if confirmed {
+            Ok(())
+        } else {
+            Err(SAError::Cancelled(format!("'{}' was not confirmed.", name)))
+        }
+    }
+
+    pub fn render(&self, cmd: &Command) -> Result<RenderedCommand, SAError> {
+        if let Some(argv) = &cmd.argv {
+            let argv: Vec<String> = argv
+                .iter()
+                .map(|arg| self.render_text(cmd, arg))
+                .collect::<Result<Vec<String>, SAError>>()?;
+
+            Ok(RenderedCommand::Argv(argv))
+        } else {
+            let command: String = self
+                .vars
+                .render_command(&self.env, cmd.command.as_deref().unwrap_or_default())
+                .map_err(|e| self.render_error(&cmd.name, e))?;
+
+            Ok(RenderedCommand::Shell(command))
+        }
+    }
+
+    fn print_header(&self, title: String, cmd: &Command) {
+        if self.settings.output != Output::Text {
+            return;
+        };
+
+        let mut header = format!("{:=<80}", format!("[SA] {} '{}' ", title, &cmd.name));
+
+        if let Some(desc) = &cmd.description {
+            header = format!("{header}\n{desc}\n{:=<80}", String::new());
+        };
+        let header: String = self.vars.redact(&header);
+
+        if self.first.replace(false) {
+            println!("{}", header.green());
+        } else {
+            println!("\n{}", header.green());
+        };
+    }
+
+    // Hash the command's inputs, and check them against the last successful run. Without a
+    // previous run, the command is up to date if its outputs are newer than its inputs.
+    fn fingerprint(
+        &self,
+        cmd: &Command,
+        rendered: &RenderedCommand,
+    ) -> Result<Option<Fingerprint>, SAError> {
+        if cmd.inputs.is_none() && cmd.outputs.is_none() {
+            return Ok(None);
+        };
+
+        let inputs: Vec<String> = cmd
+            .inputs
+            .iter()
+            .flatten()
+            .map(|input| self.render_text(cmd, input))
+            .collect::<Result<Vec<String>, SAError>>()?;
+        let outputs: Vec<String> = cmd
+            .outputs
+            .iter()
+            .flatten()
+            .map(|output| self.render_text(cmd, output))
+            .collect::<Result<Vec<String>, SAError>>()?;
+
+        let files: Vec<PathBuf> = find_files(&inputs)?;
+        let command: String = match rendered {
+            RenderedCommand::Shell(command) => command.clone(),
+            RenderedCommand::Argv(argv) => argv.join("\0"),
+        };
+        let hash: String = hash_inputs(&command, &files)?;
+
+        let up_to_date: bool = !self.settings.force
+            && outputs_exist(&outputs)?
+            && match self.cache.get(&self.shortcut.name, &cmd.key()) {
+                Some(cached) => cached == hash,
+                None => !outputs.is_empty() && outputs_newer(&files, &outputs)?,
+            };
+
+        Ok(Some(Fingerprint { hash, up_to_date }))
+    }
+
+    // Render a command, and check whether it is up to date.
+    fn render_job<'c>(&mut self, cmd: &'c Command) -> Result<Job<'c>, SAError> {
+        self.vars.item = cmd.item.clone();
+        let rendered: RenderedCommand = self.render(cmd)?;
+
+        Ok(Job {
+            cmd,
+            fingerprint: self.fingerprint(cmd, &rendered)?,
+            rendered,
+            timeout: self.shortcut.timeout(cmd),
+        })
+    }
+
+    fn confirm_command(&self, cmd: &Command) -> Result<(), SAError> {
+        if let Some(question) = &cmd.confirm {
+            let question: String = self.render_text(cmd, question)?;
+            self.confirm(&question, &cmd.name)?;
+        };
+
+        Ok(())
+    }
+
+    // Render and confirm a command, ready to be executed. Commands which are up to date aren't
+    // confirmed, as they won't be run.
+    fn prepare<'c>(&mut self, cmd: &'c Command) -> Result<Job<'c>, SAError> {
+        let job: Job = self.render_job(cmd)?;
+
+        if !job.up_to_date() {
+            self.confirm_command(cmd)?;
+        };
+
+        Ok(job)
+    }
+
+    // Record an up to date command as if it had run, without any output.
+    fn skip_command(&mut self, job: &Job) -> Result<(), SAError> {
+        if self.settings.show_header {
+            self.print_header(String::from("Up To Date"), job.cmd);
+        };
+        self.skip_record(job.cmd);
+
+        self.vars.add_command(job.cmd, CommandOutput::default());
+        self.set_variables(job.cmd)
+    }
+
+    fn run_command(&mut self, cmd: &Command) -> Result<(), SAError> {
+        let job: Job = self.render_job(cmd)?;
+        if job.up_to_date() {
+            return self.skip_command(&job);
+        };
+
+        if self.settings.show_header {
+            self.print_header(attempt_title("Running", 1, cmd), cmd);
+        };
+        self.confirm_command(cmd)?;
+        self.emit_started(cmd, &job.rendered);
+        let result: CommandOutput = execute(
+            &self.settings.shell,
+            &self.process_env,
+            &job,
+            |result, retrying| self.print_attempt(&job, result, retrying),
+        )?;
+
+        self.finish_command(&job, result)
+    }
+
+    // Run every command in a parallel group, printing each one's output once it has finished.
+    fn run_parallel(&mut self, group: &Command, members: &[Command]) -> Result<(), SAError> {
+        if self.settings.show_header {
+            self.print_header(String::from("Running Parallel"), group);
+        };
+
+        if let Some(question) = &group.confirm {
+            let question: String = self.render_text(group, question)?;
+            self.confirm(&question, &group.name)?;
+        };
+
+        // The members can't use each other's results, so they can all be prepared up front.
+        let (skipped, jobs): (Vec<Job>, Vec<Job>) = members
+            .iter()
+            .map(|cmd| self.prepare(cmd))
+            .collect::<Result<Vec<Job>, SAError>>()?
+            .into_iter()
+            .partition(|job| job.up_to_date());
+
+        for job in skipped.iter() {
+            self.skip_command(job)?;
+        }
+        for job in jobs.iter() {
+            self.emit_started(job.cmd, &job.rendered);
+        }
+        let max_jobs: usize = group.max_jobs.unwrap_or(jobs.len());
+
+        let mut results: Vec<Option<Result<CommandOutput, SAError>>> =
+            jobs.iter().map(|_| None).collect();
+
+        execute_parallel(
+            &self.settings.shell,
+            &self.process_env,
+            &jobs,
+            max_jobs,
+            |index, attempts, result| {
+                let job: &Job = &jobs[index];
+
+                if self.settings.show_header {
+                    self.print_header(attempt_title("Running", 1, job.cmd), job.cmd);
+                };
+                for (number, attempt) in attempts.iter().enumerate() {
+                    self.print_attempt(job, attempt, number + 1 < attempts.len());
+                }
+
+                results[index] = Some(result);
+            },
+        );
+
+        let mut errors: Vec<(&str, SAError)> = Vec::new();
+        for (job, result) in jobs.iter().zip(results) {
+            let finished = match result {
+                Some(result) => result.and_then(|result| self.finish_command(job, result)),
+                None => Err(SAError::CommandFailed(format!(
+                    "Command '{}' didn't finish.",
+                    job.cmd.name
+                ))),
+            };
+
+            if let Err(error) = finished {
+                errors.push((&job.cmd.name, error));
+            };
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0).1),
+            _ => {
+                let names: Vec<String> = errors
+                    .iter()
+                    .map(|(name, _)| format!("'{}'", name))
+                    .collect();
+                Err(SAError::CommandFailed(format!(
+                    "Parallel group '{}' failed, as {} failed.",
+                    group.name,
+                    names.join(", ")
+                )))
+            }
+        }
+    }
+
+    // Run the commands as a graph, starting each one once everything it needs has succeeded.
+    fn run_graph(&mut self) -> Result<(), SAError> {
+        let order: Vec<&Command> = self.shortcut.graph_order();
+
+        if self.settings.dry_run {
+            for cmd in order {
+                match self.completed.contains(&cmd.key()) {
+                    true => self.print_completed(cmd),
+                    false => self.run_step(cmd)?,
+                };
+            }
+            return Ok(());
+        };
+
+        let keys: Vec<String> = order.iter().map(|cmd| cmd.key()).collect();
+        let mut states: Vec<StepState> = vec![StepState::Waiting; order.len()];
+        for (index, cmd) in order.iter().enumerate() {
+            if self.completed.contains(&keys[index]) {
+                self.print_completed(cmd);
+                states[index] = StepState::Succeeded;
+            };
+        }
+        let mut errors: Vec<(&str, SAError)> = Vec::new();
+        let mut running: usize = 0;
+
+        let shell: &str = &self.settings.shell;
+        let process_env: HashMap<String, String> = self.process_env.clone();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| loop {
+            // The order puts needs first, so skipped commands are passed on in a single pass.
+            for (index, cmd) in order.iter().enumerate() {
+                if states[index] != StepState::Waiting {
+                    continue;
+                };
+
+                let needs: Vec<StepState> = cmd
+                    .needs()
+                    .iter()
+                    .filter_map(|need| keys.iter().position(|key| key == need))
+                    .map(|need| states[need])
+                    .collect();
+
+                if needs
+                    .iter()
+                    .any(|state| matches!(state, StepState::Failed | StepState::Skipped))
+                {
+                    states[index] = StepState::Skipped;
+                    if self.settings.show_header {
+                        self.print_header(String::from("Skipped"), cmd);
+                    };
+                    self.skip_record(cmd);
+                    continue;
+                };
+
+                if !needs.iter().all(|state| *state == StepState::Succeeded) {
+                    continue;
+                };
+
+                // Other shortcuts and loops are run straight away, while any commands already started
+                // carry on.
+                if cmd.run.is_some() || cmd.is_loop() {
+                    states[index] = match self.run_step(cmd) {
+                        Ok(()) => StepState::Succeeded,
+                        Err(error) => {
+                            errors.push((&cmd.name, error));
+                            StepState::Failed
+                        }
+                    };
+                    continue;
+                };
+
+                if running >= self.settings.jobs {
+                    continue;
+                };
+
+                match self.prepare(cmd) {
+                    Ok(job) if job.up_to_date() => {
+                        states[index] = match self.skip_command(&job) {
+                            Ok(()) => StepState::Succeeded,
+                            Err(error) => {
+                                errors.push((&cmd.name, error));
+                                StepState::Failed
+                            }
+                        };
+                    }
+                    Ok(job) => {
+                        states[index] = StepState::Running;
+                        running += 1;
+                        self.emit_started(job.cmd, &job.rendered);
+
+                        let sender = sender.clone();
+                        let process_env: &HashMap<String, String> = &process_env;
+                        scope.spawn(move || {
+                            let mut attempts: Vec<CommandOutput> = Vec::new();
+                            let result = execute(shell, process_env, &job, |result, _| {
+                                attempts.push(result.clone())
+                            });
+                            let _ = sender.send((index, job, attempts, result));
+                        });
+                    }
+                    Err(error) => {
+                        states[index] = StepState::Failed;
+                        errors.push((&cmd.name, error));
+                    }
+                };
+            }
+
+            // Saved before waiting on the next command, so an interrupted run can be resumed.
+            self.save_graph_state(&keys, &states, errors.first().map(|(name, _)| *name));
+            if running == 0 {
+                break;
+            };
+
+            // A sender is held above, so this only fails if a thread panicked.
+            let (index, job, attempts, result) = match receiver.recv() {
+                Ok(finished) => finished,
+                Err(_) => break,
+            };
+            running -= 1;
+
+            if self.settings.show_header {
+                self.print_header(attempt_title("Running", 1, job.cmd), job.cmd);
+            };
+            for (number, attempt) in attempts.iter().enumerate() {
+                self.print_attempt(&job, attempt, number + 1 < attempts.len());
+            }
+
+            match result.and_then(|result| self.finish_command(&job, result)) {
+                Ok(()) => states[index] = StepState::Succeeded,
+                Err(error) => {
+                    states[index] = StepState::Failed;
+                    errors.push((&job.cmd.name, error));
+                }
+            };
+        });
+
+        let count = |state: StepState| states.iter().filter(|step| **step == state).count();
+        if self.settings.show_footer {
+            let summary: String = format!(
+                "[SA] {} Succeeded, {} Failed, {} Skipped ",
+                count(StepState::Succeeded),
+                count(StepState::Failed),
+                count(StepState::Skipped)
+            );
+            println!("\n{}", format!("{:=<80}", summary).green());
+        };
+
+        let skipped: Vec<String> = order
+            .iter()
+            .zip(states.iter())
+            .filter(|(_, state)| **state == StepState::Skipped)
+            .map(|(cmd, _)| format!("'{}'", cmd.name))
+            .collect();
+
+        match (errors.len(), skipped.is_empty()) {
+            (0, _) => Ok(()),
+            (1, true) => Err(errors.remove(0).1),
+            _ => {
+                let failed: Vec<String> = errors
+                    .iter()
+                    .map(|(name, _)| format!("'{}'", name))
+                    .collect();
+                let mut message: String = format!(
+                    "Shortcut '{}' failed, as {} failed.",
+                    self.shortcut.name,
+                    failed.join(", ")
+                );
+                if !skipped.is_empty() {
+                    message = format!("{} Skipped {}.", message, skipped.join(", "));
+                };
+                Err(SAError::CommandFailed(message))
+            }
+        }
+    }
+
+    // Run another shortcut, passing `with` as its arguments. Its results are stored under this command.
+    fn run_shortcut(&mut self, cmd: &Command, name: &str) -> Result<(), SAError> {
+        let shortcuts: &'a HashMap<String, Shortcut> = self.shortcuts;
+        let callee: &'a Shortcut = match shortcuts.get(name) {
+            Some(callee) => callee,
+            None => {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Command '{}' runs '{}', which isn't a shortcut.",
+                    cmd.name, name
+                )))
+            }
+        };
+
+        if self.settings.show_header {
+            let title: &str = if self.settings.dry_run {
+                "Dry Run Shortcut"
+            } else {
+                "Running Shortcut"
+            };
+            self.print_header(String::from(title), cmd);
+        };
+        self.vars.item = cmd.item.clone();
+
+        if let Some(question) = &cmd.confirm {
+            let question: String = self.render_text(cmd, question)?;
+            if self.settings.dry_run {
+                if self.settings.output == Output::Text {
+                    println!("Confirm: {}", self.vars.redact(&question));
+                };
+            } else {
+                self.confirm(&question, &cmd.name)?;
+            };
+        };
+
+        let mut args: HashMap<String, String> = HashMap::new();
+        for (arg, value) in cmd.with.iter().flatten() {
+            args.insert(arg.clone(), self.render_text(cmd, value)?);
+        }
+
+        let matches: clap::ArgMatches = callee.matches_from(&args).map_err(|e| {
+            SAError::ShortcutInvalid(format!(
+                "Command '{}' can't run '{}': {}",
+                cmd.name, callee.name, e
+            ))
+        })?;
+
+        let mut runner: Runner = Runner::new(
+            self.settings,
+            shortcuts,
+            callee,
+            &self.template_dir,
+            self.cache,
+            &matches,
+        );
+        runner.first.set(false);
+        for secret in self.vars.secrets.iter() {
+            runner.vars.add_secret(secret);
+        }
+
+        let result = runner.run();
+        self.records.append(&mut runner.records);
+        self.events.append(&mut runner.events);
+        for secret in runner.vars.secrets.iter() {
+            self.vars.add_secret(secret);
+        }
+        result?;
+
+        if self.settings.dry_run {
+            self.vars.add_placeholder(cmd);
+        } else {
+            self.vars.add_command(
+                cmd,
+                CommandOutput {
+                    attempts: vec![Attempt {
+                        duration: runner.elapsed,
+                        ..Default::default()
+                    }],
+                    commands: runner.vars.commands,
+                    duration: runner.elapsed,
+                    ..Default::default()
+                },
+            );
+        };
+
+        self.set_variables(cmd)
+    }
+
+    // Print the output of an attempt, and the header of the next one if it will be retried.
+    fn print_attempt(&self, job: &Job, result: &CommandOutput, retrying: bool) {
+        if self.settings.show_body {
+            print!("{}", self.vars.redact(&result.output));
+        };
+
+        if self.settings.show_footer {
+            self.print_footer(job, result);
+        };
+
+        if retrying && self.settings.show_header {
+            self.print_header(
+                attempt_title("Retrying", result.attempts.len() as u32 + 1, job.cmd),
+                job.cmd,
+            );
+        };
+    }
+
+    // Check the result of a command, then store it for later commands.
+    fn finish_command(&mut self, job: &Job, mut result: CommandOutput) -> Result<(), SAError> {
+        let cmd: &Command = job.cmd;
+        self.vars.item = cmd.item.clone();
+        self.records.push(StepRecord {
+            name: self.vars.redact(&cmd.name),
+            status: result.status,
+            duration_ms: result.duration.as_millis() as u64,
+            ..Default::default()
+        });
+
+        let checked: Result<(), SAError> = Runner::check_result(job, &mut result);
+        self.emit(Event::StepFinished {
+            shortcut: self.shortcut.name.clone(),
+            step: cmd.key(),
+            name: self.vars.redact(&cmd.name),
+            skipped: false,
+            result: self.vars.redact_output(&result),
+        });
+        checked?;
+
+        // A cache which can't be written only means the command is run again next time.
+        if let Some(fingerprint) = &job.fingerprint {
+            let _ = self
+                .cache
+                .set(&self.shortcut.name, &cmd.key(), &fingerprint.hash);
+        };
+
+        self.vars.add_command(cmd, result);
+        self.set_variables(cmd)
+    }
+
+    // Fail if the command didn't succeed, otherwise parse its output if asked to.
+    fn check_result(job: &Job, result: &mut CommandOutput) -> Result<(), SAError> {
+        let cmd: &Command = job.cmd;
+
+        if result.timed_out {
+            return Err(SAError::CommandFailed(format!(
+                "Command '{}' timed out after {}.",
+                cmd.name,
+                humantime::format_duration(job.timeout.unwrap_or_default())
+            )));
+        };
+
+        if result.status != 0 && result.attempts.len() > 1 {
+            return Err(SAError::CommandFailed(format!(
+                "Command '{}' failed after {} attempts.",
+                cmd.name,
+                result.attempts.len()
+            )));
+        } else if result.status != 0 {
+            return Err(SAError::CommandFailed(format!(
+                "Command '{}' failed.",
+                cmd.name
+            )));
+        }
+
+        if let Some(parse) = cmd.parse {
+            match parse_output(&result.output, parse) {
+                Ok(data) => result.data = Some(data),
+                Err(e) => {
+                    return Err(SAError::OutputParse(format!(
+                        "Output of '{}' is not valid {:?}: {}",
+                        cmd.name, parse, e
+                    )))
+                }
+            };
+        };
+
+        Ok(())
+    }
+
+    fn print_footer(&self, job: &Job, result: &CommandOutput) {
+        let mut footer: String = if result.timed_out {
+            format!(
+                "[SA] Timed Out After {}",
+                humantime::format_duration(job.timeout.unwrap_or_default())
+            )
+        } else {
+            format!(
+                "[SA] Exit Code: {} in {}",
+                result.status,
+                format_duration(result.duration)
+            )
+        };
+
+        let attempts: u32 = job.cmd.attempts();
+        if attempts > 1 {
+            footer = format!(
+                "{} (Attempt {}/{})",
+                footer,
+                result.attempts.len(),
+                attempts
+            );
+        };
+
+        println!("{}", format!("{:=<80}", format!("{} ", footer)).green());
+    }
+
+    // Print what would be run, recording a placeholder in place of the command's result.
+    fn dry_run_command(&mut self, cmd: &Command) -> Result<(), SAError> {
+        if self.settings.show_header {
+            self.print_header(String::from("Dry Run"), cmd);
+        };
+        self.vars.item = cmd.item.clone();
+
+        if self.settings.output != Output::Text {
+            let rendered: RenderedCommand = self.render(cmd)?;
+            self.emit_started(cmd, &rendered);
+            self.vars.add_placeholder(cmd);
+            return self.set_variables(cmd);
+        };
+
+        let cwd: String = match env::current_dir() {
+            Ok(dir) => dir.display().to_string(),
+            Err(_) => String::from("unknown"),
+        };
+
+        if let Some(question) = &cmd.confirm {
+            println!(
+                "Confirm: {}",
+                self.vars.redact(&self.render_text(cmd, question)?)
+            );
+        };
+
+        if !cmd.needs().is_empty() {
+            println!("Needs: {}", cmd.needs().join(", "));
+        };
+
+        let rendered: RenderedCommand = self.render(cmd)?;
+        if let Some(fingerprint) = self.fingerprint(cmd, &rendered)? {
+            match fingerprint.up_to_date {
+                true => println!("Up To Date: yes"),
+                false => println!("Up To Date: no"),
+            };
+        };
+
+        match rendered {
+            RenderedCommand::Shell(command) => {
+                println!("Shell: {}", self.settings.shell);
+                println!("Cwd: {}", cwd);
+                self.print_env();
+                println!("Command:\n{}", self.vars.redact(&command));
+            }
+            RenderedCommand::Argv(argv) => {
+                println!("Shell: none");
+                println!("Cwd: {}", cwd);
+                self.print_env();
+                let argv: Vec<String> = argv.iter().map(|arg| shell_quote(arg)).collect();
+                println!("Argv:\n{}", self.vars.redact(&argv.join(" ")));
+            }
+        };
+
+        self.vars.add_placeholder(cmd);
+        self.set_variables(cmd)
+    }
+
+    fn dry_run_parallel(&mut self, group: &Command, members: &[Command]) -> Result<(), SAError> {
+        if self.settings.show_header {
+            self.print_header(String::from("Dry Run Parallel"), group);
+        };
+
+        if self.settings.output != Output::Text {
+            for cmd in members.iter() {
+                self.dry_run_command(cmd)?;
+            }
+            return Ok(());
+        };
+
+        if let Some(question) = &group.confirm {
+            println!(
+                "Confirm: {}",
+                self.vars.redact(&self.render_text(group, question)?)
+            );
+        };
+        match group.max_jobs {
+            Some(max_jobs) => println!("Max Jobs: {}", max_jobs),
+            None => println!("Max Jobs: {}", members.len()),
+        };
+
+        for cmd in members.iter() {
+            self.dry_run_command(cmd)?;
+        }
+
+        Ok(())
+    }
+
+    fn print_env(&self) {
+        let mut env_vars: Vec<(&String, &String)> = self.vars.env.iter().collect();
+        env_vars.sort();
+
+        println!("Env:");
+        for (key, value) in env_vars {
+            println!("  {}={}", key, self.vars.redact(value));
+        }
+    }
+
+    fn set_variables(&mut self, cmd: &Command) -> Result<(), SAError> {
+        if let Some(set) = &cmd.set {
+            for (name, value) in set.iter() {
+                let value: String = match value {
+                    SetValue::Template(template) => self.render_text(cmd, template)?,
+                    SetValue::Regex { .. } if self.settings.dry_run => {
+                        format!("<variables.{}>", name)
+                    }
+                    SetValue::Regex { regex, from, group } => {
+                        let text: String = match from {
+                            Some(from) => self.render_text(cmd, from)?,
+                            None => self.vars.commands[&cmd.key()].output.clone(),
+                        };
+
+                        regex_capture(&text, regex, *group).map_err(|e| {
+                            SAError::OutputParse(format!(
+                                "Couldn't set '{}' from '{}': {}",
+                                name, cmd.name, e
+                            ))
+                        })?
+                    }
+                };
+
+                self.vars.variables.insert(name.clone(), value);
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::Cache;
+    use crate::runner::{execute, execute_parallel, Job, RenderedCommand, Runner};
+    use crate::settings::Settings;
+    use crate::shortcut::{Command, Retry, Shortcut};
+    use crate::state::RunState;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    fn shell_job<'c>(cmd: &'c Command, command: &str) -> Job<'c> {
+        Job {
+            cmd,
+            rendered: RenderedCommand::Shell(command.to_string()),
+            timeout: None,
+            fingerprint: None,
+        }
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_execute_retry() {
+        let cmd = Command {
+            name: "Flaky".to_string(),
+            retry: Some(Retry {
+                attempts: 3,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Each attempt prints how many times the command has run, so they can be told apart.
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let count: String = tmp_dir.path().join("count").display().to_string();
+        let job = shell_job(
+            &cmd,
+            &format!("echo >> '{count}'; echo \"failed $(wc -l < '{count}')\" >&2; exit 1"),
+        );
+
+        let mut retries: Vec<bool> = Vec::new();
+        let result = execute("sh", &HashMap::new(), &job, |_, retrying| {
+            retries.push(retrying)
+        })
+        .unwrap();
+        assert_eq!(result.attempts.len(), 3);
+        assert_eq!(result.attempts[0].output.trim(), "failed 1");
+        assert_eq!(result.attempts[0].status, 1);
+        assert_eq!(result.attempts[2].output.trim(), "failed 3");
+        assert_eq!(result.status, 1);
+        assert_eq!(retries, vec![true, true, false]);
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_execute_parallel() {
+        let cmd = Command {
+            name: "Step".to_string(),
+            ..Default::default()
+        };
+        let jobs: Vec<Job> = vec![
+            shell_job(&cmd, "sleep 0.2; echo first"),
+            shell_job(&cmd, "echo second"),
+            shell_job(&cmd, "exit 3"),
+        ];
+
+        let mut finished: Vec<usize> = Vec::new();
+        let mut outputs: HashMap<usize, String> = HashMap::new();
+        execute_parallel(
+            "sh",
+            &HashMap::new(),
+            &jobs,
+            2,
+            |index, attempts, result| {
+                assert_eq!(attempts.len(), 1);
+                finished.push(index);
+                outputs.insert(index, result.unwrap().output);
+            },
+        );
+
+        // The slow job finishes last, as the others run alongside it.
+        assert_eq!(finished.len(), 3);
+        assert_eq!(finished.last(), Some(&0));
+        assert_eq!(outputs[&0], "first\n");
+        assert_eq!(outputs[&1], "second\n");
+    }
+
+    // A shortcut whose first step outputs a secret, and whose last step fails until `ok` exists.
+    fn secret_shortcut(dir: &Path, needs: bool) -> Shortcut {
+        let needs = |key: &str| match needs {
+            true => format!("needs: [{}]", key),
+            false => String::new(),
+        };
+        let yaml: String = format!(
+            r#"
+name: deploy
+args:
+  - arg_type: data
+    name: token
+    secret: true
+    default: hunter2
+commands:
+  - name: One
+    command: "echo tok={{{{ args.token }}}}"
+    parse: kv
+  - name: Two
+    command: "echo two"
+    {}
+  - name: Three
+    command: "test -f '{}/ok' && echo {{{{ commands.one.data.tok }}}}"
+    {}
+"#,
+            needs("one"),
+            dir.display(),
+            needs("two")
+        );
+
+        serde_yaml::from_str(&yaml).expect("Couldn't parse shortcut.")
+    }
+
+    #[test]
+    #[cfg(target_family = "unix")]
+    fn test_runner_resume_with_secret() {
+        for needs in [false, true] {
+            let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+            let shortcut: Shortcut = secret_shortcut(tmp_dir.path(), needs);
+            let shortcuts: HashMap<String, Shortcut> = HashMap::new();
+            let cache = Cache::new(&tmp_dir.path().join("cache"));
+            let state_file = RunState::path(tmp_dir.path(), "deploy");
+            let matches = shortcut.matches_from(&HashMap::new()).unwrap();
+            let settings = Settings {
+                shell: "sh".to_string(),
+                show_header: false,
+                show_body: false,
+                show_footer: false,
+                ..Settings::new()
+            };
+            let new_runner = || {
+                let mut runner = Runner::new(
+                    &settings,
+                    &shortcuts,
+                    &shortcut,
+                    tmp_dir.path(),
+                    &cache,
+                    &matches,
+                );
+                runner.keep_state(&state_file);
+                runner
+            };
+
+            assert!(new_runner().run().is_err());
+
+            // The secret result isn't saved, so its step isn't treated as completed.
+            let state = RunState::load(&state_file)
+                .unwrap()
+                .expect("No state saved.");
+            assert_eq!(state.completed, vec!["two".to_string()]);
+            assert_eq!(state.secret, vec!["one".to_string()]);
+            assert!(!state.commands.contains_key("one"));
+            assert!(state.commands.contains_key("two"));
+
+            // Skipping the step would leave later steps without its result.
+            let mut runner = new_runner();
+            runner.restore(state.clone());
+            assert!(runner.select(None, Some("Three")).is_err());
+            assert!(runner.select(Some("One"), None).is_ok());
+
+            fs::write(tmp_dir.path().join("ok"), "").expect("Couldn't write file.");
+            let mut runner = new_runner();
+            runner.restore(state);
+            runner.run().expect("Couldn't resume.");
+            assert_eq!(runner.vars.commands["three"].output, "hunter2\n");
+            assert_eq!(runner.records.len(), 3);
+            let skipped: Vec<&str> = runner
+                .records
+                .iter()
+                .filter(|record| record.skipped)
+                .map(|record| record.name.as_str())
+                .collect();
+            assert_eq!(skipped, vec!["Two"]);
+
+            tmp_dir.close().expect("Couldn't close temp_dir.");
+        }
+    }
+}
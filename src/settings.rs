@@ -1,4 +1,19 @@
 use colored::control::SHOULD_COLORIZE;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::errors::SAError;
+
+// How the results of a run are shown. `Json` prints every event as one document once the run
+// finishes, while `Jsonl` prints each event on its own line as it happens.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Output {
+    Text,
+    Json,
+    Jsonl,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Settings {
@@ -6,6 +21,51 @@ pub struct Settings {
     pub show_header: bool,
     pub show_body: bool,
     pub show_footer: bool,
+    pub show_summary: bool,
+    pub shell: String,
+    pub dry_run: bool,
+    pub assume_yes: bool,
+    pub jobs: usize,
+    pub force: bool,
+    pub output: Output,
+}
+
+// The options which can be set in the settings file within the config directory.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Deserialize)]
+struct SettingsFile {
+    shell: Option<String>,
+    jobs: Option<usize>,
+}
+
+// Run as many commands at once as there are CPUs.
+fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+#[cfg(target_family = "windows")]
+fn default_shell() -> String {
+    String::from("pwsh")
+}
+
+#[cfg(target_family = "unix")]
+fn default_shell() -> String {
+    match env::var("SHELL") {
+        Ok(shell) if !shell.is_empty() => shell,
+        _ => String::from("/bin/sh"),
+    }
+}
+
+// Environment variables take priority over the settings file, which takes priority over the default.
+fn discover_shell(configured: Option<String>) -> String {
+    if let Ok(shell) = env::var("SHORTCUT_ALIAS_SHELL") {
+        shell
+    } else if let Ok(shell) = env::var("SA_SHELL") {
+        shell
+    } else if let Some(shell) = configured {
+        shell
+    } else {
+        default_shell()
+    }
 }
 
 impl Settings {
@@ -15,54 +75,109 @@ impl Settings {
             show_header: true,
             show_body: true,
             show_footer: true,
+            show_summary: true,
+            shell: discover_shell(None),
+            dry_run: false,
+            assume_yes: false,
+            jobs: default_jobs(),
+            force: false,
+            output: Output::Text,
         }
     }
 
-    pub fn new_from_matches(args: &clap::ArgMatches) -> Settings {
+    pub fn new_from_file(filepath: &Path) -> Result<Settings, SAError> {
         let mut settings = Settings::new();
 
+        if !filepath.exists() {
+            return Ok(settings);
+        };
+
+        let content = match fs::read_to_string(filepath) {
+            Ok(file_content) => file_content,
+            Err(e) => return Err(SAError::ShortcutFileRead(filepath.to_path_buf(), e)),
+        };
+
+        let settings_file: SettingsFile = match serde_yaml::from_str(&content) {
+            Ok(file) => file,
+            Err(e) => return Err(SAError::ShortcutFileParse(filepath.to_path_buf(), e)),
+        };
+
+        settings.shell = discover_shell(settings_file.shell);
+        if let Some(jobs) = settings_file.jobs {
+            settings.jobs = jobs.max(1);
+        };
+
+        Ok(settings)
+    }
+
+    // Override the current settings with any options given on the command line.
+    pub fn update_from_matches(&mut self, args: &clap::ArgMatches) {
         if let Some(value) = args.get_one::<String>("color") {
-            if value == &String::from("on") {
-                settings.show_color = true;
-            } else {
-                settings.show_color = false;
-            };
+            self.show_color = value == &String::from("on");
         };
 
         if let Some(value) = args.get_one::<String>("header") {
-            if value == &String::from("on") {
-                settings.show_header = true;
-            } else {
-                settings.show_header = false;
-            };
+            self.show_header = value == &String::from("on");
         };
 
         if let Some(value) = args.get_one::<String>("body") {
-            if value == &String::from("on") {
-                settings.show_body = true;
-            } else {
-                settings.show_body = false;
-            };
+            self.show_body = value == &String::from("on");
         };
 
         if let Some(value) = args.get_one::<String>("footer") {
-            if value == &String::from("on") {
-                settings.show_footer = true;
-            } else {
-                settings.show_footer = false;
-            };
+            self.show_footer = value == &String::from("on");
+        };
+
+        if let Some(value) = args.get_one::<String>("summary") {
+            self.show_summary = value == &String::from("on");
         };
 
         if let Some(value) = args.get_one::<bool>("silent") {
             if *value {
-                settings.show_color = false;
-                settings.show_header = false;
-                settings.show_body = false;
-                settings.show_footer = false;
+                self.show_color = false;
+                self.show_header = false;
+                self.show_body = false;
+                self.show_footer = false;
+                self.show_summary = false;
             };
         };
 
-        settings
+        // Structured output replaces everything else which would be printed.
+        if let Some(value) = args.get_one::<String>("output") {
+            self.output = match value.as_str() {
+                "json" => Output::Json,
+                "jsonl" => Output::Jsonl,
+                _ => Output::Text,
+            };
+
+            if self.output != Output::Text {
+                self.show_color = false;
+                self.show_header = false;
+                self.show_body = false;
+                self.show_footer = false;
+                self.show_summary = false;
+            };
+        };
+
+        if let Some(value) = args.get_one::<String>("shell") {
+            self.shell = value.clone();
+        };
+
+        if let Some(value) = args.get_one::<bool>("dry_run") {
+            self.dry_run = *value;
+        };
+
+        if let Some(value) = args.get_one::<bool>("yes") {
+            self.assume_yes = *value;
+        };
+
+        if let Some(value) = args.get_one::<usize>("jobs") {
+            self.jobs = *value;
+        };
+
+        if let Some(value) = args.get_one::<bool>("force") {
+            self.force = *value;
+        };
     }
 
     // Use the value of show_color to set the terminal color override.
@@ -71,11 +186,16 @@ impl Settings {
     }
 }
 
+// Each setting is checked with `assert_eq!(.., true)` so that the expected value is explicit.
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use crate::cli::build_cli;
-    use crate::settings::Settings;
+    use crate::settings::{Output, Settings};
     use colored::control::SHOULD_COLORIZE;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
 
     #[test]
     fn test_new_settings() {
@@ -104,11 +224,121 @@ mod tests {
         let args = vec!["shortcut-alias", "--color", "off", "--footer", "off"];
 
         let matches = cli.get_matches_from(args);
-        let settings = Settings::new_from_matches(&matches);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
 
         assert_eq!(settings.show_color, false);
         assert_eq!(settings.show_header, true);
         assert_eq!(settings.show_body, true);
         assert_eq!(settings.show_footer, false);
     }
+
+    #[test]
+    fn test_settings_shell() {
+        let cli = build_cli(vec![]);
+        let args = vec!["shortcut-alias", "--shell", "/bin/bash"];
+
+        let matches = cli.get_matches_from(args);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+
+        assert_eq!(settings.shell, "/bin/bash".to_string());
+    }
+
+    #[test]
+    fn test_settings_dry_run() {
+        assert!(!Settings::new().dry_run);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--dry-run", "--yes"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+
+        assert!(settings.dry_run);
+        assert!(settings.assume_yes);
+    }
+
+    #[test]
+    fn test_settings_force() {
+        assert!(!Settings::new().force);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--force"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+        assert!(settings.force);
+    }
+
+    #[test]
+    fn test_settings_summary() {
+        assert!(Settings::new().show_summary);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--summary", "off"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+        assert!(!settings.show_summary);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--silent"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+        assert!(!settings.show_summary);
+    }
+
+    #[test]
+    fn test_settings_output() {
+        assert_eq!(Settings::new().output, Output::Text);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--output", "jsonl"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+        assert_eq!(settings.output, Output::Jsonl);
+        assert!(!settings.show_header);
+        assert!(!settings.show_body);
+        assert!(!settings.show_summary);
+
+        let cli = build_cli(vec![]);
+        assert!(cli
+            .try_get_matches_from(vec!["shortcut-alias", "--output", "xml"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_settings_jobs() {
+        assert!(Settings::new().jobs >= 1);
+
+        let cli = build_cli(vec![]);
+        let matches = cli.get_matches_from(vec!["shortcut-alias", "--jobs", "3"]);
+        let mut settings = Settings::new();
+        settings.update_from_matches(&matches);
+        assert_eq!(settings.jobs, 3);
+
+        let cli = build_cli(vec![]);
+        assert!(cli
+            .try_get_matches_from(vec!["shortcut-alias", "--jobs", "0"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_settings_from_file() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let filepath = tmp_dir.path().join(".settings.yaml");
+
+        // A missing settings file falls back to the defaults.
+        let settings = Settings::new_from_file(&filepath).expect("Couldn't load settings.");
+        assert_eq!(settings, Settings::new());
+
+        let mut settings_file = File::create(&filepath).expect("Couldn't create temporary file.");
+        writeln!(settings_file, "shell: /bin/ksh\njobs: 2")
+            .expect("Couldn't write to temporary file.");
+
+        let settings = Settings::new_from_file(&filepath).expect("Couldn't load settings.");
+        assert_eq!(settings.shell, "/bin/ksh".to_string());
+        assert_eq!(settings.jobs, 2);
+
+        drop(settings_file);
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
 }
@@ -1,13 +1,17 @@
-use core::convert::From;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use minijinja::value::Value;
+use minijinja::{context, Environment};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use crate::commands::CommandOutput;
+use crate::commands::{Attempt, CommandOutput, Parse};
 use crate::errors::SAError;
+use crate::templating::{Escape, Placeholder, Undefined, COMMAND_TEMPLATE, TEXT_TEMPLATE};
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Variables {
@@ -15,33 +19,58 @@ pub struct Variables {
     pub variables: HashMap<String, String>,
     pub env: HashMap<String, String>,
     pub commands: HashMap<String, CommandOutput>,
+    #[serde(skip)]
+    pub placeholders: Vec<String>,
+    #[serde(skip)]
+    pub secrets: Vec<String>,
+    // The item of the `for_each` or `matrix` step being run.
+    #[serde(skip)]
+    pub item: Option<LoopItem>,
 }
 
 impl Variables {
     pub fn new(shortcut: &Shortcut, cli_matches: &clap::ArgMatches) -> Variables {
         let mut vars = Variables {
             args: HashMap::new(),
-            variables: if let Some(variables) = shortcut.variables.clone() {
-                variables
-            } else {
-                HashMap::new()
-            },
+            variables: HashMap::new(),
             env: HashMap::new(),
             commands: HashMap::new(),
+            placeholders: Vec::new(),
+            secrets: Vec::new(),
+            item: None,
         };
 
+        if let Some(variables) = shortcut.variables.clone() {
+            for (name, variable) in variables {
+                if variable.secret() {
+                    vars.add_secret(variable.value());
+                };
+                vars.variables.insert(name, variable.value().to_string());
+            }
+        }
+
         if let Some(arguments) = shortcut.args.clone() {
             for arg in arguments {
-                if let Some(value) = cli_matches.get_one::<String>(&arg.name) {
+                if arg.arg_type == ArgumentType::Flag {
+                    if let Some(value) = cli_matches.get_one::<bool>(&arg.name) {
+                        vars.args.insert(arg.name.clone(), value.to_string());
+                    }
+                } else if let Some(value) = cli_matches.get_one::<String>(&arg.name) {
+                    if arg.secret.unwrap_or(false) {
+                        vars.add_secret(value);
+                    };
                     vars.args.insert(arg.name.clone(), value.clone());
                 }
             }
         }
 
         if let Some(env_vars) = shortcut.env.clone() {
-            for env_key in env_vars.iter() {
-                if let Ok(value) = env::var(env_key) {
-                    vars.env.insert(String::from(env_key), value);
+            for env_var in env_vars.iter() {
+                if let Ok(value) = env::var(env_var.name()) {
+                    if env_var.secret() {
+                        vars.add_secret(&value);
+                    };
+                    vars.env.insert(env_var.name().to_string(), value);
                 };
             }
         }
@@ -49,39 +78,215 @@ impl Variables {
         vars
     }
 
-    pub fn add_command(&mut self, command_name: String, command: CommandOutput) {
-        let cmd_name: String = command_name.replace(' ', "_").to_lowercase();
-        self.commands.insert(cmd_name, command);
+    // Load a dotenv file into `env`, returning the values so they can be passed to commands.
+    // Variables already set in the environment take precedence over the file.
+    pub fn add_env_file(&mut self, path: &Path) -> Result<HashMap<String, String>, SAError> {
+        let file_error = |e: dotenvy::Error| {
+            SAError::VariablesFile(format!("Couldn't load '{}': {}", path.display(), e))
+        };
+
+        let mut values: HashMap<String, String> = HashMap::new();
+        for item in dotenvy::from_path_iter(path).map_err(file_error)? {
+            let (key, value) = item.map_err(file_error)?;
+            if env::var_os(&key).is_none() {
+                values.insert(key, value);
+            };
+        }
+
+        self.env.extend(values.clone());
+        Ok(values)
+    }
+
+    // Load a YAML or JSON file of variables into `variables`, replacing any with the same name.
+    pub fn add_variables_file(&mut self, path: &Path) -> Result<(), SAError> {
+        let content: String = fs::read_to_string(path).map_err(|e| {
+            SAError::VariablesFile(format!("Couldn't read '{}': {}", path.display(), e))
+        })?;
+
+        let variables: HashMap<String, Variable> = serde_yaml::from_str(&content).map_err(|e| {
+            SAError::VariablesFile(format!("Couldn't parse '{}': {}", path.display(), e))
+        })?;
+
+        for (name, variable) in variables {
+            if variable.secret() {
+                self.add_secret(variable.value());
+            };
+            self.variables.insert(name, variable.value().to_string());
+        }
+
+        Ok(())
+    }
+
+    // Record a value which must never be shown.
+    pub fn add_secret(&mut self, value: &str) {
+        if !value.is_empty() && !self.secrets.iter().any(|secret| secret == value) {
+            self.secrets.push(value.to_string());
+            // Longer secrets first, so a secret containing another is hidden completely.
+            self.secrets
+                .sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        };
+    }
+
+    // Replace every secret value in the text with `****`.
+    pub fn redact(&self, text: &str) -> String {
+        let mut text: String = text.to_string();
+
+        for secret in self.secrets.iter() {
+            text = text.replace(secret, "****");
+        }
+
+        text
+    }
+
+    // A copy of the values, leaving out any which contain a secret.
+    pub fn without_secrets(&self, values: &HashMap<String, String>) -> HashMap<String, String> {
+        values
+            .iter()
+            .filter(|(_, value)| self.redact(value) == **value)
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    // Hide secrets in every string and key of parsed output. Done on the values themselves, as
+    // the JSON text may hold them escaped.
+    fn redact_value(&self, value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::String(text) => serde_json::Value::String(self.redact(text)),
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.iter()
+                    .map(|(key, item)| (self.redact(key), self.redact_value(item)))
+                    .collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+
+    // A copy of a command's result with every secret value hidden, for saving or showing it.
+    pub fn redact_output(&self, output: &CommandOutput) -> CommandOutput {
+        let data: Option<serde_json::Value> =
+            output.data.as_ref().map(|data| self.redact_value(data));
+
+        CommandOutput {
+            output: self.redact(&output.output),
+            stdout: self.redact(&output.stdout),
+            stderr: self.redact(&output.stderr),
+            data,
+            attempts: output
+                .attempts
+                .iter()
+                .map(|attempt| Attempt {
+                    output: self.redact(&attempt.output),
+                    stdout: self.redact(&attempt.stdout),
+                    stderr: self.redact(&attempt.stderr),
+                    ..attempt.clone()
+                })
+                .collect(),
+            commands: output
+                .commands
+                .iter()
+                .map(|(key, output)| (key.clone(), self.redact_output(output)))
+                .collect(),
+            ..output.clone()
+        }
+    }
+
+    pub fn add_command(&mut self, command: &Command, output: CommandOutput) {
+        self.commands.insert(command.key(), output);
     }
 
-    pub fn render_command(&self, command: &str) -> String {
-        minijinja::render!(
-            command,
+    // Record a placeholder for a command which hasn't been run, such as during a dry run.
+    pub fn add_placeholder(&mut self, command: &Command) {
+        self.placeholders.push(command.key());
+    }
+
+    fn context(&self) -> Value {
+        let mut commands: HashMap<String, Value> = self
+            .commands
+            .iter()
+            .map(|(key, output)| (key.clone(), Value::from_serialize(output)))
+            .collect();
+
+        for key in self.placeholders.iter() {
+            commands.insert(key.clone(), Placeholder::value(format!("commands.{key}")));
+        }
+
+        let (item, matrix) = match &self.item {
+            Some(LoopItem::Item(item)) => (Value::from_serialize(item), Value::UNDEFINED),
+            Some(LoopItem::Matrix(matrix)) => {
+                (Value::from_serialize(matrix), Value::from_serialize(matrix))
+            }
+            None => (Value::UNDEFINED, Value::UNDEFINED),
+        };
+
+        context! {
             args => self.args,
             variables => self.variables,
             env => self.env,
-            commands => self.commands
-        )
+            commands => commands,
+            item => item,
+            matrix => matrix,
+        }
+    }
+
+    // Render a command which will be run through the shell, applying the shortcut's autoescape.
+    pub fn render_command(
+        &self,
+        env: &Environment,
+        command: &str,
+    ) -> Result<String, minijinja::Error> {
+        env.render_named_str(COMMAND_TEMPLATE, command, self.context())
+    }
+
+    // Render text which is never escaped, such as the items of an argv list.
+    pub fn render_text(&self, env: &Environment, text: &str) -> Result<String, minijinja::Error> {
+        env.render_named_str(TEXT_TEMPLATE, text, self.context())
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub enum ArgumentType {
     #[serde(alias = "flag")]
     Flag,
+    #[default]
     #[serde(alias = "data")]
     Data,
 }
 
+// Either `prompt: true` to ask using the help text, or the question to ask.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Prompt {
+    Enabled(bool),
+    Question(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct Argument {
     pub arg_type: ArgumentType,
     pub name: String,
     pub default: Option<String>,
     pub help: Option<String>,
+    pub prompt: Option<Prompt>,
+    pub secret: Option<bool>,
+    pub choices: Option<Vec<String>>,
 }
 
 impl Argument {
+    // The question to ask if no value was given on the command line.
+    pub fn prompt_question(&self) -> Option<String> {
+        match &self.prompt {
+            Some(Prompt::Question(question)) => Some(question.clone()),
+            Some(Prompt::Enabled(true)) => Some(match &self.help {
+                Some(help) => help.clone(),
+                None => format!("Enter a value for '{}'", self.name),
+            }),
+            _ => None,
+        }
+    }
+
     pub fn argument(&self) -> clap::Arg {
         let mut arg: clap::Arg = clap::Arg::new(&self.name).long(&self.name);
         let mut action = clap::ArgAction::Set;
@@ -96,6 +301,11 @@ impl Argument {
             if let Some(default) = &self.default {
                 arg = arg.default_value(default);
             };
+
+            // Only allow one of the choices, if given.
+            if let Some(choices) = &self.choices {
+                arg = arg.value_parser(clap::builder::PossibleValuesParser::new(choices));
+            };
         };
 
         // Set help message.
@@ -110,34 +320,901 @@ impl Argument {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct Command {
     pub name: String,
     pub description: Option<String>,
-    pub command: String,
+    pub command: Option<String>,
+    pub argv: Option<Vec<String>>,
+    pub parse: Option<Parse>,
+    pub id: Option<String>,
+    pub set: Option<HashMap<String, SetValue>>,
+    pub confirm: Option<String>,
+    pub timeout: Option<String>,
+    pub retry: Option<Retry>,
+    pub parallel: Option<Vec<Command>>,
+    pub max_jobs: Option<usize>,
+    pub needs: Option<Vec<String>>,
+    pub run: Option<String>,
+    pub with: Option<HashMap<String, String>>,
+    pub inputs: Option<Vec<String>>,
+    pub outputs: Option<Vec<String>>,
+    pub for_each: Option<String>,
+    pub matrix: Option<BTreeMap<String, Vec<serde_yaml::Value>>>,
+    #[serde(skip)]
+    pub item: Option<LoopItem>,
+}
+
+// One expansion of a `for_each` or `matrix` step.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum LoopItem {
+    Item(serde_yaml::Value),
+    Matrix(BTreeMap<String, serde_yaml::Value>),
+}
+
+impl LoopItem {
+    // A short description of the item, for the command's name.
+    fn label(&self) -> String {
+        match self {
+            LoopItem::Item(item) => value_label(item),
+            LoopItem::Matrix(matrix) => matrix
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value_label(value)))
+                .collect::<Vec<String>>()
+                .join(", "),
+        }
+    }
+}
+
+fn value_label(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(text) => text.clone(),
+        serde_yaml::Value::Number(number) => number.to_string(),
+        serde_yaml::Value::Bool(boolean) => boolean.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+// Read the items of a rendered `for_each`, either as a YAML or JSON list, or as a comma or
+// newline separated list.
+pub fn parse_items(text: &str) -> Vec<serde_yaml::Value> {
+    if let Ok(items) = serde_yaml::from_str::<Vec<serde_yaml::Value>>(text) {
+        return items;
+    };
+
+    text.split([',', '\n'])
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| serde_yaml::Value::String(item.to_string()))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    #[default]
+    Fixed,
+    Exponential,
+}
+
+// How to retry a failed command.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Option<String>,
+    pub backoff: Option<Backoff>,
+    pub on_exit_codes: Option<Vec<i32>>,
+}
+
+impl Retry {
+    // Retry on the given exit codes, or on any failure if none are given.
+    pub fn should_retry(&self, status: i32) -> bool {
+        match &self.on_exit_codes {
+            Some(codes) => codes.contains(&status),
+            None => status != 0,
+        }
+    }
+
+    // How long to wait before an attempt. The delay is checked by validate.
+    pub fn delay_before(&self, attempt: u32) -> Duration {
+        let delay: Duration = self
+            .delay
+            .as_ref()
+            .and_then(|delay| parse_duration(delay).ok())
+            .unwrap_or_default();
+
+        match self.backoff.unwrap_or_default() {
+            Backoff::Fixed => delay,
+            // Double the delay after each retry.
+            Backoff::Exponential => {
+                delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(2)))
+            }
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), SAError> {
+        if self.attempts == 0 {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' needs at least 1 retry attempt.",
+                name
+            )));
+        };
+
+        if let Some(delay) = &self.delay {
+            parse_duration(delay).map_err(|e| {
+                SAError::ShortcutInvalid(format!(
+                    "Command '{}' has an invalid retry delay '{}': {}",
+                    name, delay, e
+                ))
+            })?;
+        };
+
+        Ok(())
+    }
 }
 
+// A value to store in `variables` once a command has finished.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SetValue {
+    Template(String),
+    Regex {
+        regex: String,
+        from: Option<String>,
+        group: Option<usize>,
+    },
+}
+
+// Find the first match of the pattern. Defaults to the first capture group, if the pattern has one.
+pub fn regex_capture(text: &str, pattern: &str, group: Option<usize>) -> Result<String, String> {
+    let regex = match Regex::new(pattern) {
+        Ok(r) => r,
+        Err(e) => return Err(format!("Invalid regex '{pattern}': {e}")),
+    };
+
+    let group: usize = group.unwrap_or(if regex.captures_len() > 1 { 1 } else { 0 });
+
+    match regex.captures(text) {
+        Some(captures) => match captures.get(group) {
+            Some(value) => Ok(value.as_str().to_string()),
+            None => Err(format!("Regex '{pattern}' has no group {group}.")),
+        },
+        None => Err(format!("Regex '{pattern}' didn't match.")),
+    }
+}
+
+// How a shortcut is shown in errors about `extends`.
+fn describe(shortcut: &Shortcut) -> String {
+    format!("'{}' ({})", shortcut.name, shortcut.path.display())
+}
+
+// Merge the base into the named shortcut, after resolving the base itself.
+fn resolve_shortcut(
+    shortcuts: &mut HashMap<String, Shortcut>,
+    name: &str,
+    path: &mut Vec<String>,
+) -> Result<(), SAError> {
+    let base_name: String = match &shortcuts[name].extends {
+        Some(base_name) => base_name.clone(),
+        None => return Ok(()),
+    };
+
+    if let Some(start) = path.iter().position(|step| step == name) {
+        let mut cycle: Vec<String> = path[start..]
+            .iter()
+            .map(|step| describe(&shortcuts[step]))
+            .collect();
+        cycle.push(describe(&shortcuts[name]));
+
+        return Err(SAError::ShortcutInvalid(format!(
+            "Shortcuts extend each other in a cycle: {}.",
+            cycle.join(" -> ")
+        )));
+    };
+
+    if !shortcuts.contains_key(&base_name) {
+        return Err(SAError::ShortcutInvalid(format!(
+            "Shortcut {} extends '{}', which isn't a shortcut.",
+            describe(&shortcuts[name]),
+            base_name
+        )));
+    };
+
+    path.push(name.to_string());
+    resolve_shortcut(shortcuts, &base_name, path)?;
+    path.pop();
+
+    let base: Shortcut = shortcuts[&base_name].clone();
+    if let Some(shortcut) = shortcuts.get_mut(name) {
+        shortcut.inherit(&base);
+        shortcut.validate()?;
+    };
+
+    Ok(())
+}
+
+// Merge every shortcut using `extends` with its base. Needs every shortcut to have been loaded.
+pub fn resolve_extends(shortcuts: &mut HashMap<String, Shortcut>) -> Result<(), SAError> {
+    let mut names: Vec<String> = shortcuts.keys().cloned().collect();
+    names.sort();
+
+    for name in names.iter() {
+        resolve_shortcut(shortcuts, name, &mut Vec::new())?;
+    }
+
+    Ok(())
+}
+
+// Follow the shortcuts run by the named shortcut, returning the names making up a cycle if one is found.
+fn find_run_cycle(
+    shortcuts: &HashMap<String, Shortcut>,
+    name: &str,
+    path: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|step| step == name) {
+        let mut cycle: Vec<String> = path[start..].to_vec();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    };
+
+    path.push(name.to_string());
+    if let Some(shortcut) = shortcuts.get(name) {
+        for command in shortcut.all_commands() {
+            if let Some(cycle) = command
+                .run
+                .as_ref()
+                .and_then(|run| find_run_cycle(shortcuts, run, path))
+            {
+                return Some(cycle);
+            };
+        }
+    };
+    path.pop();
+
+    None
+}
+
+// Check every shortcut run by a command exists, and that no shortcut ends up running itself.
+pub fn validate_runs(shortcuts: &HashMap<String, Shortcut>) -> Result<(), SAError> {
+    for shortcut in shortcuts.values() {
+        for command in shortcut.all_commands() {
+            if let Some(run) = &command.run {
+                if !shortcuts.contains_key(run) {
+                    return Err(SAError::ShortcutInvalid(format!(
+                        "Command '{}' in shortcut '{}' runs '{}', which isn't a shortcut.",
+                        command.name, shortcut.name, run
+                    )));
+                };
+            };
+        }
+
+        if let Some(cycle) = find_run_cycle(shortcuts, &shortcut.name, &mut Vec::new()) {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Shortcut '{}' runs itself: {}.",
+                shortcut.name,
+                cycle.join(" -> ")
+            )));
+        };
+    }
+
+    Ok(())
+}
+
+// Parse a human readable duration, such as `30s` or `1m 30s`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    humantime::parse_duration(text).map_err(|e| e.to_string())
+}
+
+fn is_identifier(value: &str) -> bool {
+    let mut chars = value.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+impl Command {
+    // The key the command's output is stored under in `commands`.
+    pub fn key(&self) -> String {
+        match &self.id {
+            Some(id) => id.clone(),
+            None => self.name.replace(' ', "_").to_lowercase(),
+        }
+    }
+
+    // A command must either be run through the shell, or run directly from an argv list.
+    pub fn validate(&self) -> Result<(), SAError> {
+        if let Some(timeout) = &self.timeout {
+            parse_duration(timeout).map_err(|e| {
+                SAError::ShortcutInvalid(format!(
+                    "Command '{}' has an invalid timeout '{}': {}",
+                    self.name, timeout, e
+                ))
+            })?;
+        };
+
+        if let Some(retry) = &self.retry {
+            retry.validate(&self.name)?;
+        };
+
+        let kinds: usize = [
+            self.command.is_some(),
+            self.argv.is_some(),
+            self.parallel.is_some(),
+            self.run.is_some(),
+        ]
+        .iter()
+        .filter(|kind| **kind)
+        .count();
+
+        if kinds == 0 {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' needs one of 'command', 'argv', 'parallel' or 'run'.",
+                self.name
+            )));
+        } else if kinds > 1 {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' can only have one of 'command', 'argv', 'parallel' or 'run'.",
+                self.name
+            )));
+        };
+
+        if self.with.is_some() && self.run.is_none() {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' can only use 'with' alongside 'run'.",
+                self.name
+            )));
+        };
+
+        if (self.inputs.is_some() || self.outputs.is_some())
+            && (self.parallel.is_some() || self.run.is_some())
+        {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' can only use 'inputs' and 'outputs' alongside 'command' or 'argv'.",
+                self.name
+            )));
+        };
+
+        self.validate_loop()?;
+
+        if let Some(members) = &self.parallel {
+            return self.validate_parallel(members);
+        };
+
+        match (&self.argv, &self.id) {
+            (Some(argv), _) if argv.is_empty() => Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' has an empty 'argv'.",
+                self.name
+            ))),
+            (_, Some(id)) if !is_identifier(id) => Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' has an invalid id '{}'. Use only letters, numbers and underscores.",
+                self.name, id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // The keys of the commands which must succeed before this one runs.
+    pub fn needs(&self) -> &[String] {
+        self.needs.as_deref().unwrap_or_default()
+    }
+
+    // The most times the command will be run.
+    pub fn attempts(&self) -> u32 {
+        self.retry.as_ref().map_or(1, |retry| retry.attempts)
+    }
+
+    // Whether the command is run once per item of `for_each` or `matrix`.
+    pub fn is_loop(&self) -> bool {
+        self.for_each.is_some() || self.matrix.is_some()
+    }
+
+    // Every combination of the values in `matrix`, in order of the keys.
+    pub fn matrix_items(&self) -> Vec<LoopItem> {
+        let mut combinations: Vec<BTreeMap<String, serde_yaml::Value>> = vec![BTreeMap::new()];
+
+        for (key, values) in self.matrix.iter().flatten() {
+            combinations = combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.insert(key.clone(), value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+
+        combinations.into_iter().map(LoopItem::Matrix).collect()
+    }
+
+    // A copy of the command for each item, stored under `<id>_<index>`.
+    pub fn expand(&self, items: Vec<LoopItem>) -> Vec<Command> {
+        let key: String = self.key();
+
+        items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| Command {
+                name: format!("{} ({})", self.name, item.label()),
+                id: Some(format!("{}_{}", key, index)),
+                for_each: None,
+                matrix: None,
+                max_jobs: None,
+                needs: None,
+                item: Some(item),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    fn validate_loop(&self) -> Result<(), SAError> {
+        if self.for_each.is_some() && self.matrix.is_some() {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' can only have one of 'for_each' or 'matrix'.",
+                self.name
+            )));
+        };
+
+        if !self.is_loop() {
+            return Ok(());
+        };
+
+        if self.parallel.is_some() {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Parallel group '{}' can't use 'for_each' or 'matrix'.",
+                self.name
+            )));
+        };
+
+        if let Some((key, _)) = self
+            .matrix
+            .iter()
+            .flatten()
+            .find(|(_, values)| values.is_empty())
+        {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' has no values for '{}' in its 'matrix'.",
+                self.name, key
+            )));
+        };
+
+        // YAML reads `3.10` as the number 3.1, so decimals must be quoted to keep them as written.
+        for (key, values) in self.matrix.iter().flatten() {
+            if let Some(value) = values.iter().find(|value| value.is_f64()) {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Command '{}' has the number {} for '{}' in its 'matrix'. Quote it, such as \"3.10\", so it is used as written.",
+                    self.name,
+                    value.as_f64().unwrap_or_default(),
+                    key
+                )));
+            };
+        }
+
+        match (self.max_jobs, &self.run) {
+            (Some(0), _) => Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' needs a 'max_jobs' of at least 1.",
+                self.name
+            ))),
+            (Some(_), Some(_)) => Err(SAError::ShortcutInvalid(format!(
+                "Command '{}' can't run another shortcut in parallel.",
+                self.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_parallel(&self, members: &[Command]) -> Result<(), SAError> {
+        if self.max_jobs == Some(0) {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Parallel group '{}' needs a 'max_jobs' of at least 1.",
+                self.name
+            )));
+        };
+
+        for member in members.iter() {
+            if member.parallel.is_some() {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Parallel group '{}' can't contain another parallel group '{}'.",
+                    self.name, member.name
+                )));
+            };
+            if member.run.is_some() {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Command '{}' in parallel group '{}' can't run another shortcut.",
+                    member.name, self.name
+                )));
+            };
+            if member.needs.is_some() {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Command '{}' in parallel group '{}' can't use 'needs'.",
+                    member.name, self.name
+                )));
+            };
+            if member.is_loop() {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Command '{}' in parallel group '{}' can't use 'for_each' or 'matrix'.",
+                    member.name, self.name
+                )));
+            };
+            member.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+// Either the name of an environment variable, or the name and whether it is secret.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvVar {
+    Name(String),
+    Detailed { name: String, secret: Option<bool> },
+}
+
+impl EnvVar {
+    pub fn name(&self) -> &str {
+        match self {
+            EnvVar::Name(name) => name,
+            EnvVar::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn secret(&self) -> bool {
+        match self {
+            EnvVar::Name(_) => false,
+            EnvVar::Detailed { secret, .. } => secret.unwrap_or(false),
+        }
+    }
+}
+
+// Either a static value, or the value and whether it is secret.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Variable {
+    Value(String),
+    Detailed { value: String, secret: Option<bool> },
+}
+
+impl Variable {
+    pub fn value(&self) -> &str {
+        match self {
+            Variable::Value(value) => value,
+            Variable::Detailed { value, .. } => value,
+        }
+    }
+
+    pub fn secret(&self) -> bool {
+        match self {
+            Variable::Value(_) => false,
+            Variable::Detailed { secret, .. } => secret.unwrap_or(false),
+        }
+    }
+}
+
+// Either the path to a file, or the path and whether it may be missing.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IncludeFile {
+    Path(String),
+    Detailed {
+        path: String,
+        optional: Option<bool>,
+    },
+}
+
+impl IncludeFile {
+    pub fn path(&self) -> &str {
+        match self {
+            IncludeFile::Path(path) => path,
+            IncludeFile::Detailed { path, .. } => path,
+        }
+    }
+
+    pub fn optional(&self) -> bool {
+        match self {
+            IncludeFile::Path(_) => false,
+            IncludeFile::Detailed { optional, .. } => optional.unwrap_or(false),
+        }
+    }
+
+    // The same file, with its path made relative to the given directory.
+    fn relative_to(&self, dir: &Path) -> IncludeFile {
+        let path: String = dir.join(self.path()).display().to_string();
+
+        match self {
+            IncludeFile::Path(_) => IncludeFile::Path(path),
+            IncludeFile::Detailed { optional, .. } => IncludeFile::Detailed {
+                path,
+                optional: *optional,
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct Shortcut {
     pub name: String,
     pub description: Option<String>,
     pub args: Option<Vec<Argument>>,
-    pub env: Option<Vec<String>>,
-    pub variables: Option<HashMap<String, String>>,
+    pub env: Option<Vec<EnvVar>>,
+    pub variables: Option<HashMap<String, Variable>>,
+    pub autoescape: Option<Escape>,
+    pub undefined: Option<Undefined>,
+    pub confirm: Option<String>,
+    pub timeout: Option<String>,
+    pub env_files: Option<Vec<IncludeFile>>,
+    pub variables_from: Option<Vec<IncludeFile>>,
+    pub extends: Option<String>,
+    pub commands_before: Option<Vec<Command>>,
+    #[serde(default)]
     pub commands: Vec<Command>,
+    pub commands_after: Option<Vec<Command>>,
+    // The file the shortcut was loaded from.
+    #[serde(skip)]
+    pub path: PathBuf,
 }
 
-impl Shortcut {
-    pub fn new(filepath: &PathBuf) -> Result<Shortcut, SAError> {
-        let content = match fs::read_to_string(filepath) {
-            Ok(file_content) => file_content,
-            Err(e) => return Err(SAError::ShortcutFileRead(e)),
+impl Shortcut {
+    pub fn new(filepath: &PathBuf) -> Result<Shortcut, SAError> {
+        let content = match fs::read_to_string(filepath) {
+            Ok(file_content) => file_content,
+            Err(e) => return Err(SAError::ShortcutFileRead(filepath.clone(), e)),
+        };
+
+        let mut shortcut: Shortcut = match serde_yaml::from_str(&content) {
+            Ok(short) => short,
+            Err(e) => return Err(SAError::ShortcutFileParse(filepath.clone(), e)),
+        };
+        shortcut.path = filepath.clone();
+
+        // Shortcuts extending another are checked once the base has been merged in.
+        if shortcut.extends.is_none() {
+            shortcut.wrap_commands();
+            shortcut.validate()?;
+        };
+
+        Ok(shortcut)
+    }
+
+    // Move `commands_before` and `commands_after` into the commands.
+    fn wrap_commands(&mut self) {
+        let mut commands: Vec<Command> = self.commands_before.take().unwrap_or_default();
+        commands.append(&mut self.commands);
+        commands.append(&mut self.commands_after.take().unwrap_or_default());
+        self.commands = commands;
+    }
+
+    // Fill in anything not set from the base shortcut. Args, env and variables are merged,
+    // with this shortcut's taking priority, and the base's commands are used if there are none.
+    fn inherit(&mut self, base: &Shortcut) {
+        self.description = self.description.take().or(base.description.clone());
+        self.autoescape = self.autoescape.or(base.autoescape);
+        self.undefined = self.undefined.or(base.undefined);
+        self.confirm = self.confirm.take().or(base.confirm.clone());
+        self.timeout = self.timeout.take().or(base.timeout.clone());
+
+        if let Some(base_args) = &base.args {
+            let mut args: Vec<Argument> = base_args.clone();
+            for arg in self.args.take().unwrap_or_default() {
+                args.retain(|base_arg| base_arg.name != arg.name);
+                args.push(arg);
+            }
+            self.args = Some(args);
+        };
+
+        if let Some(base_env) = &base.env {
+            let mut env: Vec<EnvVar> = base_env.clone();
+            for env_var in self.env.take().unwrap_or_default() {
+                env.retain(|base_var| base_var.name() != env_var.name());
+                env.push(env_var);
+            }
+            self.env = Some(env);
+        };
+
+        if let Some(base_variables) = &base.variables {
+            let mut variables: HashMap<String, Variable> = base_variables.clone();
+            variables.extend(self.variables.take().unwrap_or_default());
+            self.variables = Some(variables);
+        };
+
+        // The base's files are relative to the base, so are kept pointing at the same place.
+        let base_dir: PathBuf = std::path::absolute(base.dir()).unwrap_or(base.dir().to_path_buf());
+        let inherit_files = |base_files: &Option<Vec<IncludeFile>>,
+                             files: Option<Vec<IncludeFile>>| {
+            let mut all: Vec<IncludeFile> = base_files
+                .iter()
+                .flatten()
+                .map(|file| file.relative_to(&base_dir))
+                .collect();
+            all.extend(files.unwrap_or_default());
+            (!all.is_empty()).then_some(all)
+        };
+        self.env_files = inherit_files(&base.env_files, self.env_files.take());
+        self.variables_from = inherit_files(&base.variables_from, self.variables_from.take());
+
+        if self.commands.is_empty() {
+            self.commands = base.commands.clone();
+        };
+
+        self.extends = None;
+        self.wrap_commands();
+    }
+
+    // The directory files such as `env_files` are relative to.
+    pub fn dir(&self) -> &Path {
+        match self.path.parent() {
+            Some(dir) => dir,
+            None => Path::new("."),
+        }
+    }
+
+    // The timeout for a command, falling back to the shortcut's. Both are checked by validate.
+    pub fn timeout(&self, command: &Command) -> Option<Duration> {
+        command
+            .timeout
+            .as_ref()
+            .or(self.timeout.as_ref())
+            .and_then(|timeout| parse_duration(timeout).ok())
+    }
+
+    // Every command, including those inside parallel groups.
+    pub fn all_commands(&self) -> Vec<&Command> {
+        let mut commands: Vec<&Command> = Vec::new();
+
+        for command in self.commands.iter() {
+            commands.push(command);
+            commands.extend(command.parallel.iter().flatten());
+        }
+
+        commands
+    }
+
+    // Whether the commands are run as a graph using `needs`, rather than in order.
+    pub fn uses_needs(&self) -> bool {
+        self.commands.iter().any(|command| command.needs.is_some())
+    }
+
+    // The top level steps, in the order they are run.
+    pub fn steps(&self) -> Vec<&Command> {
+        if self.uses_needs() {
+            self.graph_order()
+        } else {
+            self.commands.iter().collect()
+        }
+    }
+
+    // The commands in an order where each one comes after everything it needs.
+    // Commands in a cycle are left out, but validate doesn't allow cycles.
+    pub fn graph_order(&self) -> Vec<&Command> {
+        let mut order: Vec<&Command> = Vec::new();
+
+        while order.len() < self.commands.len() {
+            let next: Option<&Command> = self.commands.iter().find(|command| {
+                !order.iter().any(|done| std::ptr::eq(*done, *command))
+                    && command
+                        .needs()
+                        .iter()
+                        .all(|need| order.iter().any(|done| &done.key() == need))
+            });
+
+            match next {
+                Some(command) => order.push(command),
+                None => break,
+            };
+        }
+
+        order
+    }
+
+    // Follow `needs` from the key, returning the keys making up a cycle if one is found.
+    fn find_cycle(
+        &self,
+        key: &str,
+        path: &mut Vec<String>,
+        checked: &mut Vec<String>,
+    ) -> Option<Vec<String>> {
+        if let Some(start) = path.iter().position(|step| step == key) {
+            let mut cycle: Vec<String> = path[start..].to_vec();
+            cycle.push(key.to_string());
+            return Some(cycle);
+        };
+
+        if checked.iter().any(|step| step == key) {
+            return None;
+        };
+
+        path.push(key.to_string());
+        if let Some(command) = self.commands.iter().find(|command| command.key() == key) {
+            for need in command.needs() {
+                if let Some(cycle) = self.find_cycle(need, path, checked) {
+                    return Some(cycle);
+                };
+            }
+        };
+        path.pop();
+        checked.push(key.to_string());
+
+        None
+    }
+
+    fn validate_needs(&self) -> Result<(), SAError> {
+        let keys: Vec<String> = self.commands.iter().map(Command::key).collect();
+
+        for command in self.commands.iter() {
+            if command.parallel.is_some() && self.uses_needs() {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Shortcut '{}' can't use both 'needs' and parallel groups.",
+                    self.name
+                )));
+            };
+
+            for need in command.needs() {
+                if !keys.contains(need) {
+                    return Err(SAError::ShortcutInvalid(format!(
+                        "Command '{}' needs '{}', which isn't a command in shortcut '{}'.",
+                        command.name, need, self.name
+                    )));
+                };
+            }
+        }
+
+        let mut checked: Vec<String> = Vec::new();
+        for key in keys.iter() {
+            if let Some(cycle) = self.find_cycle(key, &mut Vec::new(), &mut checked) {
+                return Err(SAError::ShortcutInvalid(format!(
+                    "Shortcut '{}' has a cycle in 'needs': {}.",
+                    self.name,
+                    cycle.join(" -> ")
+                )));
+            };
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), SAError> {
+        let mut ids: Vec<&String> = Vec::new();
+
+        if self.commands.is_empty() {
+            return Err(SAError::ShortcutInvalid(format!(
+                "Shortcut {} has no commands.",
+                describe(self)
+            )));
         };
 
-        match serde_yaml::from_str(&content) {
-            Ok(short) => Ok(short),
-            Err(e) => Err(SAError::ShortcutFileParse(e)),
+        if let Some(timeout) = &self.timeout {
+            parse_duration(timeout).map_err(|e| {
+                SAError::ShortcutInvalid(format!(
+                    "Shortcut '{}' has an invalid timeout '{}': {}",
+                    self.name, timeout, e
+                ))
+            })?;
+        };
+
+        for command in self.commands.iter() {
+            command.validate()?;
+        }
+
+        self.validate_needs()?;
+
+        for command in self.all_commands() {
+            if let Some(id) = &command.id {
+                if ids.contains(&id) {
+                    return Err(SAError::ShortcutInvalid(format!(
+                        "Shortcut '{}' has more than one command with the id '{}'.",
+                        self.name, id
+                    )));
+                };
+                ids.push(id);
+            };
         }
+
+        Ok(())
     }
 
     pub fn command(&self) -> clap::Command {
@@ -157,17 +1234,68 @@ impl Shortcut {
 
         command
     }
+
+    // Parse argument values as if they were given on the command line, so they are checked the
+    // same way. Flags are set by a value of `true`.
+    pub fn matches_from(&self, args: &HashMap<String, String>) -> Result<clap::ArgMatches, String> {
+        let mut argv: Vec<String> = Vec::new();
+
+        for (name, value) in args.iter() {
+            let is_flag: bool = self
+                .args
+                .iter()
+                .flatten()
+                .any(|arg| &arg.name == name && arg.arg_type == ArgumentType::Flag);
+
+            // Given joined to the option, so values starting with a dash aren't read as options.
+            if !is_flag {
+                argv.push(format!("--{}={}", name, value));
+            } else if value == "true" {
+                argv.push(format!("--{}", name));
+            };
+        }
+
+        self.command()
+            .no_binary_name(true)
+            .try_get_matches_from(argv)
+            .map_err(|e| {
+                let message: String = e.to_string();
+                message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ")
+                    .to_string()
+            })
+    }
 }
 
+// Some of these tests were written before clippy's lints for the patterns they use.
 #[cfg(test)]
+#[allow(
+    dropping_references,
+    clippy::expect_fun_call,
+    clippy::match_like_matches_macro,
+    clippy::needless_borrow,
+    clippy::single_component_path_imports,
+    clippy::useless_conversion
+)]
 mod tests {
     use crate::cli::build_cli;
     use crate::commands::CommandOutput;
-    use crate::shortcut::{Argument, ArgumentType, Command, Shortcut, Variables};
+    use crate::errors::SAError;
+    use crate::shortcut::{
+        parse_items, regex_capture, resolve_extends, validate_runs, Argument, ArgumentType,
+        Backoff, Command, EnvVar, IncludeFile, LoopItem, Prompt, Retry, Shortcut, Variable,
+        Variables,
+    };
+    use crate::templating::environment;
     use clap::ArgAction;
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
     use std::fs::File;
     use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
     use tempdir;
     use tmp_env;
 
@@ -220,16 +1348,33 @@ commands:
             name: "version".to_string(),
             description: None,
             args: Some(vec![simple_argument()]),
-            env: Some(vec!["ENV_VARIABLE".to_string()]),
+            env: Some(vec![EnvVar::Name("ENV_VARIABLE".to_string())]),
             variables: Some(HashMap::from([(
                 "VARIABLE".to_string(),
-                "world".to_string(),
+                Variable::Value("world".to_string()),
             )])),
+            autoescape: None,
+            undefined: None,
+            confirm: None,
             commands: vec![Command {
                 name: "Python Version".to_string(),
                 description: None,
-                command: "{{ args.bin }} -V".to_string(),
+                command: Some("{{ args.bin }} -V".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn simple_shortcut_struct(name: &str) -> Shortcut {
+        Shortcut {
+            name: name.to_string(),
+            commands: vec![Command {
+                name: "Echo".to_string(),
+                command: Some("echo".to_string()),
+                ..Default::default()
             }],
+            ..Default::default()
         }
     }
 
@@ -239,6 +1384,7 @@ commands:
             name: "bin".to_string(),
             default: Some("python".to_string()),
             help: Some("The Python binary to use.".to_string()),
+            ..Default::default()
         }
     }
 
@@ -246,8 +1392,7 @@ commands:
         Argument {
             arg_type: ArgumentType::Flag,
             name: "color".to_string(),
-            default: None,
-            help: None,
+            ..Default::default()
         }
     }
 
@@ -265,6 +1410,7 @@ commands:
             Some("Get the Cargo and Rust Versions.".to_string())
         );
         assert_eq!(shortcut.commands.len(), 2);
+        assert_eq!(shortcut.dir(), tmp_dir.directory.path());
 
         drop(shortcut_file);
         tmp_dir.close();
@@ -287,6 +1433,38 @@ commands:
         tmp_dir.close();
     }
 
+    #[test]
+    fn test_shortcut_matches_from() {
+        let mut shortcut = adv_shortcut();
+        shortcut
+            .args
+            .as_mut()
+            .expect("No args configured.")
+            .push(simple_argument_flag());
+
+        let matches = shortcut
+            .matches_from(&HashMap::from([
+                ("bin".to_string(), "python3".to_string()),
+                ("color".to_string(), "true".to_string()),
+            ]))
+            .expect("Couldn't parse arguments.");
+        assert_eq!(
+            matches.get_one::<String>("bin"),
+            Some(&"python3".to_string())
+        );
+        assert_eq!(matches.get_one::<bool>("color"), Some(&true));
+
+        let matches = shortcut
+            .matches_from(&HashMap::from([("bin".to_string(), "-x hi".to_string())]))
+            .expect("Couldn't parse arguments.");
+        assert_eq!(matches.get_one::<String>("bin"), Some(&"-x hi".to_string()));
+
+        let error = shortcut
+            .matches_from(&HashMap::from([("missing".to_string(), "x".to_string())]))
+            .expect_err("Unknown arguments should fail.");
+        assert!(error.contains("--missing"));
+    }
+
     #[test]
     fn test_argument_get_argument() {
         let arg = simple_argument();
@@ -309,6 +1487,68 @@ commands:
         });
     }
 
+    #[test]
+    fn test_argument_prompt_question() {
+        let mut arg = simple_argument();
+        assert_eq!(arg.prompt_question(), None);
+
+        arg.prompt = Some(Prompt::Enabled(false));
+        assert_eq!(arg.prompt_question(), None);
+
+        arg.prompt = Some(Prompt::Enabled(true));
+        assert_eq!(
+            arg.prompt_question(),
+            Some("The Python binary to use.".to_string())
+        );
+
+        arg.help = None;
+        assert_eq!(
+            arg.prompt_question(),
+            Some("Enter a value for 'bin'".to_string())
+        );
+
+        arg.prompt = Some(Prompt::Question("Which Python?".to_string()));
+        assert_eq!(arg.prompt_question(), Some("Which Python?".to_string()));
+    }
+
+    #[test]
+    fn test_argument_choices() {
+        let shortcut = Shortcut {
+            name: "deploy".to_string(),
+            args: Some(vec![Argument {
+                name: "env".to_string(),
+                choices: Some(vec!["dev".to_string(), "prod".to_string()]),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let cli = build_cli(vec![&shortcut]);
+        assert!(cli
+            .clone()
+            .try_get_matches_from(["shortcut-alias", "deploy", "--env", "prod"])
+            .is_ok());
+        assert!(cli
+            .try_get_matches_from(["shortcut-alias", "deploy", "--env", "staging"])
+            .is_err());
+    }
+
+    #[test]
+    fn test_new_variables_flag() {
+        let shortcut = Shortcut {
+            name: "version".to_string(),
+            args: Some(vec![simple_argument_flag()]),
+            ..Default::default()
+        };
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from(["shortcut-alias", "version", "--color"]);
+
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+
+        let variables = Variables::new(&shortcut, sub_matches);
+        assert_eq!(variables.args.get("color"), Some(&"true".to_string()));
+    }
+
     #[test]
     fn test_new_variables() {
         let _tmp_env = tmp_env::set_var("ENV_VARIABLE", "some_variable");
@@ -317,8 +1557,7 @@ commands:
         let cli = build_cli(vec![&shortcut]);
         let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
 
-        let (_, sub_matches) = matches.subcommand()
-            .expect("No subcommand specified.");
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
 
         let variables = Variables::new(&shortcut, &sub_matches);
 
@@ -338,6 +1577,148 @@ commands:
         );
     }
 
+    #[test]
+    fn test_variables_secrets() {
+        let _tmp_env = tmp_env::set_var("SECRET_ENV_VARIABLE", "env-secret");
+
+        let shortcut: Shortcut = serde_yaml::from_str(
+            "
+name: deploy
+args:
+  - arg_type: data
+    name: token
+    secret: true
+  - arg_type: data
+    name: user
+env:
+  - name: SECRET_ENV_VARIABLE
+    secret: true
+  - ENV_VARIABLE
+variables:
+  password:
+    value: hunter2
+    secret: true
+  host: example.com
+commands: []
+",
+        )
+        .unwrap();
+        assert_eq!(
+            shortcut.env.clone().unwrap()[1],
+            EnvVar::Name("ENV_VARIABLE".to_string())
+        );
+        assert_eq!(
+            shortcut.variables.clone().unwrap()["host"],
+            Variable::Value("example.com".to_string())
+        );
+
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from([
+            "shortcut-alias",
+            "deploy",
+            "--token",
+            "abc123",
+            "--user",
+            "matt",
+        ]);
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+
+        let mut variables = Variables::new(&shortcut, sub_matches);
+        assert_eq!(
+            variables.variables.get("password"),
+            Some(&"hunter2".to_string())
+        );
+
+        assert_eq!(
+            variables.redact("matt:abc123@example.com hunter2 env-secret"),
+            "matt:****@example.com **** ****"
+        );
+
+        // Secrets containing other secrets are hidden completely.
+        variables.add_secret("abc");
+        variables.add_secret("");
+        assert_eq!(variables.redact("abc123 abc"), "**** ****");
+
+        let output = CommandOutput {
+            output: "token=abc123\n".to_string(),
+            data: Some(serde_json::json!({"token": "abc123"})),
+            commands: HashMap::from([(
+                "login".to_string(),
+                CommandOutput {
+                    output: "hunter2".to_string(),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let redacted = variables.redact_output(&output);
+        assert_eq!(redacted.output, "token=****\n");
+        assert_eq!(redacted.data, Some(serde_json::json!({"token": "****"})));
+        assert_eq!(redacted.commands["login"].output, "****");
+
+        // Secrets which would be escaped in JSON are still found in parsed output.
+        variables.add_secret("p\"w\\d");
+        let output = CommandOutput {
+            data: Some(serde_json::json!({"login": ["p\"w\\d", 3], "p\"w\\d": true})),
+            ..Default::default()
+        };
+        let redacted = variables.redact_output(&output);
+        assert_eq!(
+            redacted.data,
+            Some(serde_json::json!({"login": ["****", 3], "****": true}))
+        );
+    }
+
+    #[test]
+    fn test_variables_add_files() {
+        let tmp_dir = TempDir::new("shortcut_alias".to_string());
+        let env_file = tmp_dir.create_file(
+            ".env",
+            "DEPLOY_REGION=eu-west-1\nDEPLOY_USER=\"deploy bot\"".to_string(),
+        );
+        let vars_file = tmp_dir.create_file(
+            "prod.yaml",
+            "VARIABLE: replaced\ntoken:\n  value: abc123\n  secret: true".to_string(),
+        );
+        let bad_file = tmp_dir.create_file("bad.yaml", "- not a map".to_string());
+
+        let shortcut = adv_shortcut();
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from(["shortcut-alias", "version"]);
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+        let mut variables = Variables::new(&shortcut, sub_matches);
+
+        let values = variables
+            .add_env_file(&tmp_dir.directory.path().join(".env"))
+            .unwrap();
+        assert_eq!(values.get("DEPLOY_USER"), Some(&"deploy bot".to_string()));
+        assert_eq!(
+            variables.env.get("DEPLOY_REGION"),
+            Some(&"eu-west-1".to_string())
+        );
+
+        variables
+            .add_variables_file(&tmp_dir.directory.path().join("prod.yaml"))
+            .unwrap();
+        assert_eq!(
+            variables.variables.get("VARIABLE"),
+            Some(&"replaced".to_string())
+        );
+        assert_eq!(variables.redact("abc123"), "****");
+
+        assert!(variables
+            .add_variables_file(&tmp_dir.directory.path().join("bad.yaml"))
+            .is_err());
+        assert!(variables
+            .add_env_file(&tmp_dir.directory.path().join("missing.env"))
+            .is_err());
+
+        drop(env_file);
+        drop(vars_file);
+        drop(bad_file);
+        tmp_dir.close();
+    }
+
     #[test]
     fn test_variables_add_command() {
         let _tmp_env = tmp_env::set_var("ENV_VARIABLE", "some_variable");
@@ -346,23 +1727,38 @@ commands:
         let cli = build_cli(vec![&shortcut]);
         let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
 
-        let (_, sub_matches) = matches.subcommand()
-            .expect("No subcommand specified.");
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
 
         let mut variables = Variables::new(&shortcut, &sub_matches);
 
         assert_eq!(variables.commands.len(), 0);
 
         variables.add_command(
-            "Python_Version".to_string(),
+            &Command {
+                name: "Python_Version".to_string(),
+                ..Default::default()
+            },
             CommandOutput {
                 output: "python 3.10.0".to_string(),
                 status: 0,
+                ..Default::default()
             },
         );
 
         assert_eq!(variables.commands.len(), 1);
         assert!(variables.commands.contains_key("python_version"));
+
+        variables.add_command(
+            &Command {
+                name: "Python Version (again)".to_string(),
+                id: Some("pyVersion".to_string()),
+                ..Default::default()
+            },
+            CommandOutput::default(),
+        );
+
+        assert_eq!(variables.commands.len(), 2);
+        assert!(variables.commands.contains_key("pyVersion"));
     }
 
     #[test]
@@ -372,13 +1768,602 @@ commands:
         let shortcut = adv_shortcut();
         let cli = build_cli(vec![&shortcut]);
         let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
-    
-        let (_, sub_matches) = matches.subcommand()
-            .expect("No subcommand specified.");
+
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
 
         let variables = Variables::new(&shortcut, &sub_matches);
 
-        let rendered = variables.render_command(&shortcut.commands[0].command);
-        assert_eq!(rendered, "python -V".to_string());
+        let env = environment(&shortcut, Path::new("templates"));
+        let rendered =
+            variables.render_command(&env, shortcut.commands[0].command.as_ref().unwrap());
+        assert_eq!(rendered.unwrap(), "python -V".to_string());
+
+        assert!(variables
+            .render_command(&env, "{{ args.bnary }} -V")
+            .is_err());
+    }
+
+    #[test]
+    fn test_variables_render_command_data() {
+        let shortcut = adv_shortcut();
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
+
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+
+        let mut variables = Variables::new(&shortcut, sub_matches);
+        variables.add_command(
+            &Command {
+                name: "Get Pods".to_string(),
+                ..Default::default()
+            },
+            CommandOutput {
+                output: r#"{"items": [{"name": "web"}, {"name": "db"}]}"#.to_string(),
+                status: 0,
+                data: Some(serde_json::json!({"items": [{"name": "web"}, {"name": "db"}]})),
+                ..Default::default()
+            },
+        );
+
+        let env = environment(&shortcut, Path::new("templates"));
+        let rendered = variables.render_command(
+            &env,
+            "{% for pod in commands.get_pods.data['items'] %}{{ pod.name }} {% endfor %}",
+        );
+        assert_eq!(rendered.unwrap(), "web db ".to_string());
+    }
+
+    #[test]
+    fn test_variables_render_placeholder() {
+        let shortcut = adv_shortcut();
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
+
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+
+        let mut variables = Variables::new(&shortcut, sub_matches);
+        variables.add_placeholder(&shortcut.commands[0]);
+
+        let env = environment(&shortcut, Path::new("templates"));
+        let rendered = variables.render_command(&env, "echo {{ commands.python_version.output }}");
+        assert_eq!(
+            rendered.unwrap(),
+            "echo <commands.python_version.output>".to_string()
+        );
+    }
+
+    #[test]
+    fn test_command_validate() {
+        let mut command = Command {
+            name: "Git Commit".to_string(),
+            argv: Some(vec!["git".to_string(), "commit".to_string()]),
+            ..Default::default()
+        };
+        assert!(command.validate().is_ok());
+
+        command.command = Some("git commit".to_string());
+        assert!(command.validate().is_err());
+
+        command.argv = None;
+        assert!(command.validate().is_ok());
+
+        command.command = None;
+        assert!(command.validate().is_err());
+
+        command.argv = Some(vec![]);
+        assert!(command.validate().is_err());
+
+        command.argv = Some(vec!["git".to_string()]);
+        command.id = Some("git_commit".to_string());
+        assert!(command.validate().is_ok());
+
+        command.id = Some("git-commit".to_string());
+        assert!(command.validate().is_err());
+    }
+
+    #[test]
+    fn test_command_validate_parallel() {
+        let member = Command {
+            name: "Lint".to_string(),
+            id: Some("lint".to_string()),
+            command: Some("cargo clippy".to_string()),
+            ..Default::default()
+        };
+        let mut shortcut = Shortcut {
+            name: "check".to_string(),
+            commands: vec![Command {
+                name: "Checks".to_string(),
+                max_jobs: Some(2),
+                parallel: Some(vec![member.clone()]),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(shortcut.validate().is_ok());
+        assert_eq!(shortcut.all_commands().len(), 2);
+
+        // Ids must be unique across parallel groups too.
+        shortcut.commands.push(member.clone());
+        assert!(shortcut.validate().is_err());
+        shortcut.commands.pop();
+
+        shortcut.commands[0].max_jobs = Some(0);
+        assert!(shortcut.validate().is_err());
+        shortcut.commands[0].max_jobs = None;
+
+        shortcut.commands[0].command = Some("make".to_string());
+        assert!(shortcut.validate().is_err());
+        shortcut.commands[0].command = None;
+
+        shortcut.commands[0].outputs = Some(vec!["target".to_string()]);
+        assert!(shortcut.validate().is_err());
+        shortcut.commands[0].outputs = None;
+
+        let nested = shortcut.commands[0].clone();
+        shortcut.commands[0].parallel = Some(vec![nested]);
+        assert!(shortcut.validate().is_err());
+    }
+
+    #[test]
+    fn test_command_loop() {
+        let yaml = |value: &str| serde_yaml::Value::from(value);
+        assert_eq!(
+            parse_items("[api, 2]"),
+            vec![yaml("api"), serde_yaml::Value::from(2)]
+        );
+        assert_eq!(
+            parse_items("api, web\ndb\n"),
+            vec![yaml("api"), yaml("web"), yaml("db")]
+        );
+        assert!(parse_items("").is_empty());
+
+        let mut cmd = Command {
+            name: "Test".to_string(),
+            command: Some("tox -e py{{ matrix.python }}".to_string()),
+            matrix: Some(BTreeMap::from([
+                ("os".to_string(), vec![yaml("linux"), yaml("mac")]),
+                ("python".to_string(), vec![yaml("3.10"), yaml("3.11")]),
+            ])),
+            ..Default::default()
+        };
+        assert!(cmd.validate().is_ok());
+
+        // Unquoted, `3.10` is read as 3.1, so decimal numbers aren't allowed.
+        let mut unquoted = cmd.clone();
+        unquoted.matrix = serde_yaml::from_str("{python: [3.10, 3.11], shard: [1, 2]}").unwrap();
+        assert!(unquoted.validate().is_err());
+        unquoted.matrix = serde_yaml::from_str("{python: ['3.10'], shard: [1, 2]}").unwrap();
+        assert!(unquoted.validate().is_ok());
+
+        let steps = cmd.expand(cmd.matrix_items());
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[1].name, "Test (os=linux, python=3.11)");
+        assert_eq!(steps[1].key(), "test_1");
+        assert!(!steps[1].is_loop());
+        assert_eq!(
+            steps[1].item,
+            Some(LoopItem::Matrix(BTreeMap::from([
+                ("os".to_string(), yaml("linux")),
+                ("python".to_string(), yaml("3.11")),
+            ])))
+        );
+
+        cmd.for_each = Some("{{ args.services }}".to_string());
+        assert!(cmd.validate().is_err());
+        cmd.matrix = None;
+        assert!(cmd.validate().is_ok());
+
+        cmd.max_jobs = Some(0);
+        assert!(cmd.validate().is_err());
+        cmd.max_jobs = Some(2);
+        assert!(cmd.validate().is_ok());
+
+        cmd.inputs = Some(vec!["src/**/*.rs".to_string()]);
+        assert!(cmd.validate().is_ok());
+
+        let steps = cmd.expand(vec![LoopItem::Item(yaml("api"))]);
+        assert_eq!(steps[0].name, "Test (api)");
+        assert_eq!(steps[0].max_jobs, None);
+    }
+
+    #[test]
+    fn test_variables_render_item() {
+        let shortcut = adv_shortcut();
+        let cli = build_cli(vec![&shortcut]);
+        let matches = cli.get_matches_from(["shortcut-alias", "version", "--bin", "python"]);
+
+        let (_, sub_matches) = matches.subcommand().expect("No subcommand specified.");
+
+        let mut variables = Variables::new(&shortcut, sub_matches);
+        let env = environment(&shortcut, Path::new("templates"));
+
+        variables.item = Some(LoopItem::Item(serde_yaml::Value::from("api")));
+        let rendered = variables.render_command(&env, "deploy {{ item }}");
+        assert_eq!(rendered.unwrap(), "deploy api".to_string());
+        assert!(variables.render_command(&env, "{{ matrix.os }}").is_err());
+
+        variables.item = Some(LoopItem::Matrix(BTreeMap::from([(
+            "os".to_string(),
+            serde_yaml::Value::from("linux"),
+        )])));
+        let rendered = variables.render_command(&env, "{{ matrix.os }} {{ item.os }}");
+        assert_eq!(rendered.unwrap(), "linux linux".to_string());
+    }
+
+    #[test]
+    fn test_shortcut_needs() {
+        let step = |name: &str, needs: &[&str]| Command {
+            name: name.to_string(),
+            command: Some(format!("echo {}", name)),
+            needs: Some(needs.iter().map(|need| need.to_string()).collect()),
+            ..Default::default()
+        };
+        let mut shortcut = Shortcut {
+            name: "release".to_string(),
+            commands: vec![
+                step("publish", &["build", "test"]),
+                step("test", &["build"]),
+                step("build", &[]),
+                step("docs", &[]),
+            ],
+            ..Default::default()
+        };
+        assert!(shortcut.uses_needs());
+        assert!(shortcut.validate().is_ok());
+
+        let order: Vec<String> = shortcut
+            .graph_order()
+            .iter()
+            .map(|command| command.key())
+            .collect();
+        assert_eq!(order, vec!["build", "test", "publish", "docs"]);
+
+        shortcut.commands[2].needs = Some(vec!["publish".to_string()]);
+        match shortcut.validate() {
+            Err(SAError::ShortcutInvalid(message)) => {
+                assert!(message.contains("publish -> build -> publish"))
+            }
+            _ => panic!("The cycle wasn't found."),
+        };
+
+        shortcut.commands[2].needs = Some(vec!["lint".to_string()]);
+        assert!(shortcut.validate().is_err());
+
+        shortcut.commands[2].needs = None;
+        shortcut.commands[3] = Command {
+            name: "Checks".to_string(),
+            parallel: Some(vec![Command {
+                name: "Lint".to_string(),
+                command: Some("cargo clippy".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert!(shortcut.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_runs() {
+        let runs = |name: &str, run: &str| Shortcut {
+            name: name.to_string(),
+            commands: vec![Command {
+                name: format!("Run {}", run),
+                run: Some(run.to_string()),
+                with: Some(HashMap::from([("env".to_string(), "prod".to_string())])),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut shortcuts: HashMap<String, Shortcut> = HashMap::from([
+            ("release".to_string(), runs("release", "deploy")),
+            ("deploy".to_string(), runs("deploy", "push")),
+            ("push".to_string(), simple_shortcut_struct("push")),
+        ]);
+        assert!(shortcuts["release"].validate().is_ok());
+        assert!(validate_runs(&shortcuts).is_ok());
+
+        shortcuts.insert("push".to_string(), runs("push", "release"));
+        match validate_runs(&shortcuts) {
+            Err(SAError::ShortcutInvalid(message)) => assert!(message.contains("runs itself")),
+            _ => panic!("The cycle wasn't found."),
+        };
+
+        shortcuts.insert("push".to_string(), runs("push", "missing"));
+        assert!(validate_runs(&shortcuts).is_err());
+
+        // `with` is only allowed alongside `run`.
+        let mut command = Command {
+            name: "Echo".to_string(),
+            command: Some("echo".to_string()),
+            with: Some(HashMap::new()),
+            ..Default::default()
+        };
+        assert!(command.validate().is_err());
+
+        command.run = Some("push".to_string());
+        assert!(command.validate().is_err());
+
+        command.command = None;
+        assert!(command.validate().is_ok());
+    }
+
+    #[test]
+    fn test_resolve_extends() {
+        let base: Shortcut = serde_yaml::from_str(
+            "
+name: base
+description: The base shortcut.
+args:
+  - arg_type: data
+    name: env
+    default: dev
+  - arg_type: data
+    name: region
+    default: eu
+variables:
+  app: web
+  replicas: \"1\"
+env_files: [.env]
+commands:
+  - name: Build
+    command: make
+",
+        )
+        .unwrap();
+        let child: Shortcut = serde_yaml::from_str(
+            "
+name: child
+extends: base
+args:
+  - arg_type: data
+    name: env
+    default: prod
+variables:
+  replicas: \"3\"
+commands_before:
+  - name: Check
+    command: make check
+commands_after:
+  - name: Push
+    command: make push
+    needs: [build]
+",
+        )
+        .unwrap();
+
+        let mut shortcuts: HashMap<String, Shortcut> = HashMap::from([
+            (
+                "base".to_string(),
+                Shortcut {
+                    path: PathBuf::from("/config/base.yaml"),
+                    ..base
+                },
+            ),
+            (
+                "child".to_string(),
+                Shortcut {
+                    path: PathBuf::from("/config/apps/child.yaml"),
+                    ..child
+                },
+            ),
+        ]);
+        assert!(resolve_extends(&mut shortcuts).is_ok());
+
+        let child: &Shortcut = &shortcuts["child"];
+        assert_eq!(child.extends, None);
+        assert_eq!(child.description, Some("The base shortcut.".to_string()));
+
+        let args: Vec<(&str, Option<&str>)> = child
+            .args
+            .iter()
+            .flatten()
+            .map(|arg| (arg.name.as_str(), arg.default.as_deref()))
+            .collect();
+        assert_eq!(args, vec![("region", Some("eu")), ("env", Some("prod"))]);
+
+        let variables = child.variables.clone().unwrap();
+        assert_eq!(variables["app"], Variable::Value("web".to_string()));
+        assert_eq!(variables["replicas"], Variable::Value("3".to_string()));
+
+        assert_eq!(
+            child.env_files,
+            Some(vec![IncludeFile::Path("/config/.env".to_string())])
+        );
+
+        let names: Vec<&str> = child.commands.iter().map(|cmd| cmd.name.as_str()).collect();
+        assert_eq!(names, vec!["Check", "Build", "Push"]);
+
+        // Missing bases and cycles are reported with the files involved.
+        let mut broken: HashMap<String, Shortcut> = HashMap::from([(
+            "child".to_string(),
+            Shortcut {
+                name: "child".to_string(),
+                extends: Some("missing".to_string()),
+                path: PathBuf::from("/config/child.yaml"),
+                ..Default::default()
+            },
+        )]);
+        match resolve_extends(&mut broken) {
+            Err(SAError::ShortcutInvalid(message)) => {
+                assert!(message.contains("/config/child.yaml"))
+            }
+            _ => panic!("The missing base wasn't found."),
+        };
+
+        broken.insert(
+            "missing".to_string(),
+            Shortcut {
+                name: "missing".to_string(),
+                extends: Some("child".to_string()),
+                path: PathBuf::from("/config/missing.yaml"),
+                ..Default::default()
+            },
+        );
+        match resolve_extends(&mut broken) {
+            Err(SAError::ShortcutInvalid(message)) => {
+                assert!(message.contains("cycle"));
+                assert!(message.contains("/config/missing.yaml"));
+            }
+            _ => panic!("The cycle wasn't found."),
+        };
+    }
+
+    #[test]
+    fn test_shortcut_timeout() {
+        let mut shortcut = Shortcut {
+            name: "deploy".to_string(),
+            timeout: Some("1m".to_string()),
+            commands: vec![
+                Command {
+                    name: "Build".to_string(),
+                    command: Some("make".to_string()),
+                    timeout: Some("30s".to_string()),
+                    ..Default::default()
+                },
+                Command {
+                    name: "Push".to_string(),
+                    command: Some("make push".to_string()),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert!(shortcut.validate().is_ok());
+        assert_eq!(
+            shortcut.timeout(&shortcut.commands[0]),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            shortcut.timeout(&shortcut.commands[1]),
+            Some(Duration::from_secs(60))
+        );
+
+        shortcut.timeout = None;
+        assert_eq!(shortcut.timeout(&shortcut.commands[1]), None);
+
+        shortcut.commands[0].timeout = Some("soon".to_string());
+        assert!(matches!(
+            shortcut.validate(),
+            Err(SAError::ShortcutInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_retry() {
+        let mut retry = Retry {
+            attempts: 4,
+            delay: Some("1s".to_string()),
+            ..Default::default()
+        };
+        assert!(retry.should_retry(1));
+        assert!(!retry.should_retry(0));
+        assert_eq!(retry.delay_before(2), Duration::from_secs(1));
+        assert_eq!(retry.delay_before(4), Duration::from_secs(1));
+
+        retry.backoff = Some(Backoff::Exponential);
+        assert_eq!(retry.delay_before(2), Duration::from_secs(1));
+        assert_eq!(retry.delay_before(3), Duration::from_secs(2));
+        assert_eq!(retry.delay_before(4), Duration::from_secs(4));
+
+        retry.on_exit_codes = Some(vec![75, 124]);
+        assert!(retry.should_retry(124));
+        assert!(!retry.should_retry(1));
+
+        let mut command = Command {
+            name: "Fetch".to_string(),
+            command: Some("curl example.com".to_string()),
+            retry: Some(retry),
+            ..Default::default()
+        };
+        assert!(command.validate().is_ok());
+
+        command.retry = Some(Retry {
+            attempts: 0,
+            ..Default::default()
+        });
+        assert!(command.validate().is_err());
+
+        command.retry = Some(Retry {
+            attempts: 2,
+            delay: Some("later".to_string()),
+            ..Default::default()
+        });
+        assert!(command.validate().is_err());
+    }
+
+    #[test]
+    fn test_shortcut_validate_duplicate_ids() {
+        let command = Command {
+            name: "Build".to_string(),
+            command: Some("cargo build".to_string()),
+            id: Some("build".to_string()),
+            ..Default::default()
+        };
+        let mut shortcut = Shortcut {
+            name: "build".to_string(),
+            commands: vec![command.clone()],
+            ..Default::default()
+        };
+        assert!(shortcut.validate().is_ok());
+
+        shortcut.commands.push(command);
+        assert!(shortcut.validate().is_err());
+    }
+
+    #[test]
+    fn test_shortcut_validate_no_commands() {
+        let tmp_dir = tempdir::TempDir::new("test_no_commands").unwrap();
+        let path = tmp_dir.path().join("empty.yaml");
+        std::fs::write(&path, "name: empty\n").unwrap();
+        match Shortcut::new(&path) {
+            Err(SAError::ShortcutInvalid(message)) => {
+                assert!(message.contains("has no commands"));
+                assert!(message.contains("empty.yaml"));
+            }
+            _ => panic!("A shortcut without commands was loaded."),
+        };
+
+        // A shortcut extending one without commands has none to inherit.
+        let mut shortcuts: HashMap<String, Shortcut> = HashMap::from([
+            (
+                "base".to_string(),
+                Shortcut {
+                    name: "base".to_string(),
+                    extends: Some("root".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "root".to_string(),
+                Shortcut {
+                    name: "root".to_string(),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        match resolve_extends(&mut shortcuts) {
+            Err(SAError::ShortcutInvalid(message)) => {
+                assert!(message.contains("'base'"));
+                assert!(message.contains("has no commands"));
+            }
+            _ => panic!("A shortcut without commands was resolved."),
+        };
+    }
+
+    #[test]
+    fn test_regex_capture() {
+        let output = "Python 3.10.4\n";
+
+        assert_eq!(regex_capture(output, r"\d+\.\d+", None).unwrap(), "3.10");
+        assert_eq!(
+            regex_capture(output, r"Python (\d+)\.(\d+)", None).unwrap(),
+            "3"
+        );
+        assert_eq!(
+            regex_capture(output, r"Python (\d+)\.(\d+)", Some(2)).unwrap(),
+            "10"
+        );
+        assert!(regex_capture(output, r"Ruby (\d+)", None).is_err());
+        assert!(regex_capture(output, r"Python (\d+)", Some(3)).is_err());
     }
 }
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::CommandOutput;
+use crate::errors::SAError;
+use crate::shortcut::Shortcut;
+
+// The progress of the last run of a shortcut, so it can be resumed if it failed.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct RunState {
+    pub shortcut: String,
+    // The arguments the shortcut was run with, apart from any secrets.
+    pub args: HashMap<String, String>,
+    pub variables: HashMap<String, String>,
+    pub commands: HashMap<String, CommandOutput>,
+    // The keys of the steps which succeeded.
+    pub completed: Vec<String>,
+    // The name of the step which failed, if any.
+    pub failed: Option<String>,
+    // The keys of the steps which succeeded, but whose results held a secret so weren't saved.
+    #[serde(default)]
+    pub secret: Vec<String>,
+}
+
+impl RunState {
+    pub fn path(state_dir: &Path, shortcut: &str) -> PathBuf {
+        state_dir.join(format!("{}.json", shortcut))
+    }
+
+    // Load the state of the last run, or None if the shortcut hasn't been run.
+    pub fn load(path: &Path) -> Result<Option<RunState>, SAError> {
+        if !path.exists() {
+            return Ok(None);
+        };
+
+        let content: String = fs::read_to_string(path)
+            .map_err(|e| SAError::State(format!("Couldn't read '{}': {}", path.display(), e)))?;
+
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| SAError::State(format!("Couldn't parse '{}': {}", path.display(), e)))
+    }
+
+    // Whether the run got to the end without anything failing. A run which was interrupted has
+    // no failed step, but some steps aren't completed.
+    pub fn finished(&self, shortcut: &Shortcut) -> bool {
+        self.failed.is_none()
+            && shortcut
+                .steps()
+                .iter()
+                .all(|cmd| self.completed.contains(&cmd.key()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SAError> {
+        let write_error =
+            |e: String| SAError::State(format!("Couldn't write '{}': {}", path.display(), e));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| write_error(e.to_string()))?;
+        };
+
+        let content: String =
+            serde_json::to_string_pretty(self).map_err(|e| write_error(e.to_string()))?;
+        fs::write(path, content).map_err(|e| write_error(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{Attempt, CommandOutput};
+    use crate::shortcut::{Command, Shortcut};
+    use crate::state::RunState;
+    use std::collections::HashMap;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_run_state() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        let path = RunState::path(&tmp_dir.path().join("state"), "deploy");
+        assert_eq!(path, tmp_dir.path().join("state/deploy.json"));
+
+        assert_eq!(RunState::load(&path).expect("Couldn't load state."), None);
+
+        let state = RunState {
+            shortcut: "deploy".to_string(),
+            args: HashMap::from([("env".to_string(), "prod".to_string())]),
+            commands: HashMap::from([(
+                "build".to_string(),
+                CommandOutput {
+                    output: "built\n".to_string(),
+                    attempts: vec![Attempt::default()],
+                    ..Default::default()
+                },
+            )]),
+            completed: vec!["build".to_string()],
+            failed: Some("Push".to_string()),
+            ..Default::default()
+        };
+        state.save(&path).expect("Couldn't save state.");
+
+        assert_eq!(
+            RunState::load(&path).expect("Couldn't load state."),
+            Some(state)
+        );
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+
+    #[test]
+    fn test_run_state_finished() {
+        let shortcut = Shortcut {
+            commands: vec![
+                Command {
+                    name: "Build".to_string(),
+                    ..Default::default()
+                },
+                Command {
+                    name: "Push".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        // Interrupted after the first step.
+        let mut state = RunState {
+            completed: vec!["build".to_string()],
+            ..Default::default()
+        };
+        assert!(!state.finished(&shortcut));
+
+        state.completed.push("push".to_string());
+        assert!(state.finished(&shortcut));
+
+        state.failed = Some("Push".to_string());
+        assert!(!state.finished(&shortcut));
+    }
+}
@@ -0,0 +1,570 @@
+use minijinja::value::{Enumerator, Object, ObjectRepr, Value};
+use minijinja::{
+    escape_formatter, path_loader, AutoEscape, Environment, Error, ErrorKind, Output,
+    UndefinedBehavior,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::shortcut::Shortcut;
+
+// Template names used to decide which templates are auto escaped.
+pub const COMMAND_TEMPLATE: &str = "command";
+pub const TEXT_TEMPLATE: &str = "text";
+
+const SHELL_ESCAPE: &str = "shell";
+const POWERSHELL_ESCAPE: &str = "powershell";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Escape {
+    #[default]
+    None,
+    Shell,
+    Powershell,
+}
+
+impl Escape {
+    fn auto_escape(&self) -> AutoEscape {
+        match self {
+            Escape::None => AutoEscape::None,
+            Escape::Shell => AutoEscape::Custom(SHELL_ESCAPE),
+            Escape::Powershell => AutoEscape::Custom(POWERSHELL_ESCAPE),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Undefined {
+    #[default]
+    Strict,
+    SemiStrict,
+    Lenient,
+    Chainable,
+}
+
+impl Undefined {
+    fn behavior(&self) -> UndefinedBehavior {
+        match self {
+            Undefined::Strict => UndefinedBehavior::Strict,
+            Undefined::SemiStrict => UndefinedBehavior::SemiStrict,
+            Undefined::Lenient => UndefinedBehavior::Lenient,
+            Undefined::Chainable => UndefinedBehavior::Chainable,
+        }
+    }
+}
+
+// Stands in for a value which isn't known yet, such as the result of a command during a dry run.
+// Any attribute of a placeholder is another placeholder, so templates render without errors.
+#[derive(Debug)]
+pub struct Placeholder {
+    path: String,
+}
+
+impl Placeholder {
+    pub fn value(path: String) -> Value {
+        Value::from_object(Placeholder { path })
+    }
+}
+
+impl Object for Placeholder {
+    fn repr(self: &Arc<Self>) -> ObjectRepr {
+        ObjectRepr::Iterable
+    }
+
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        Some(Placeholder::value(format!("{}.{}", self.path, key)))
+    }
+
+    fn enumerate(self: &Arc<Self>) -> Enumerator {
+        Enumerator::Empty
+    }
+
+    fn is_true(self: &Arc<Self>) -> bool {
+        true
+    }
+
+    fn render(self: &Arc<Self>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.path)
+    }
+}
+
+// Characters which never need quoting in a POSIX shell.
+fn is_shell_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c)
+}
+
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_shell_safe) {
+        return value.to_string();
+    };
+
+    format!("'{}'", value.replace('\'', r#"'"'"'"#))
+}
+
+pub fn powershell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// Quote a value for use inside double quotes, such as `export NAME="..."` or a dotenv file.
+pub fn env_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+
+    for c in value.chars() {
+        if matches!(c, '\\' | '"' | '$' | '`') {
+            quoted.push('\\');
+        };
+        quoted.push(c);
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+fn shell_quote_filter(value: &Value) -> Value {
+    Value::from_safe_string(shell_quote(&value.to_string()))
+}
+
+fn powershell_quote_filter(value: &Value) -> Value {
+    Value::from_safe_string(powershell_quote(&value.to_string()))
+}
+
+fn env_quote_filter(value: &Value) -> Value {
+    Value::from_safe_string(env_quote(&value.to_string()))
+}
+
+fn join_quoted_filter(values: &Value, separator: Option<String>) -> Result<Value, Error> {
+    let quoted: Vec<String> = values
+        .try_iter()?
+        .map(|value| shell_quote(&value.to_string()))
+        .collect();
+
+    Ok(Value::from_safe_string(
+        quoted.join(separator.as_deref().unwrap_or(" ")),
+    ))
+}
+
+fn invalid_operation(message: String) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message)
+}
+
+fn now_function(format: Option<String>) -> Result<String, Error> {
+    let now = chrono::Local::now();
+
+    match format {
+        // Writing the date fails on an invalid format, where `to_string` would panic.
+        Some(format) => {
+            let mut text = String::new();
+            fmt::Write::write_fmt(&mut text, format_args!("{}", now.format(&format))).map_err(
+                |_| invalid_operation(format!("'{format}' is not a valid date format.")),
+            )?;
+            Ok(text)
+        }
+        None => Ok(now.to_rfc3339()),
+    }
+}
+
+fn uuid_function() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn hostname_function() -> Result<String, Error> {
+    match hostname::get() {
+        Ok(name) => Ok(name.to_string_lossy().to_string()),
+        Err(e) => Err(invalid_operation(format!("Couldn't get the hostname: {e}"))),
+    }
+}
+
+fn current_dir() -> Result<PathBuf, Error> {
+    env::current_dir()
+        .map_err(|e| invalid_operation(format!("Couldn't get the current directory: {e}")))
+}
+
+fn cwd_function() -> Result<String, Error> {
+    Ok(current_dir()?.display().to_string())
+}
+
+// Find the closest parent directory containing `.git`, without needing git to be installed.
+fn git_root_function() -> Result<String, Error> {
+    let cwd: PathBuf = current_dir()?;
+
+    match cwd.ancestors().find(|dir| dir.join(".git").exists()) {
+        Some(root) => Ok(root.display().to_string()),
+        None => Err(invalid_operation(format!(
+            "'{}' is not inside a git repository.",
+            cwd.display()
+        ))),
+    }
+}
+
+fn read_file_function(path: String) -> Result<String, Error> {
+    fs::read_to_string(&path).map_err(|e| invalid_operation(format!("Couldn't read '{path}': {e}")))
+}
+
+fn env_or_function(name: String, default: Option<String>) -> String {
+    env::var(name).unwrap_or(default.unwrap_or_default())
+}
+
+fn basename_filter(path: String) -> String {
+    match Path::new(&path).file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => String::new(),
+    }
+}
+
+fn dirname_filter(path: String) -> String {
+    match Path::new(&path).parent() {
+        Some(parent) => parent.display().to_string(),
+        None => String::new(),
+    }
+}
+
+fn abspath_filter(path: String) -> Result<String, Error> {
+    match std::path::absolute(&path) {
+        Ok(absolute) => Ok(absolute.display().to_string()),
+        Err(e) => Err(invalid_operation(format!(
+            "Couldn't make '{path}' absolute: {e}"
+        ))),
+    }
+}
+
+fn regex_replace_filter(
+    value: String,
+    pattern: String,
+    replacement: String,
+) -> Result<String, Error> {
+    match Regex::new(&pattern) {
+        Ok(regex) => Ok(regex.replace_all(&value, replacement.as_str()).to_string()),
+        Err(e) => Err(invalid_operation(format!("Invalid regex '{pattern}': {e}"))),
+    }
+}
+
+fn from_json_filter(value: String) -> Result<Value, Error> {
+    match serde_json::from_str::<serde_json::Value>(&value) {
+        Ok(parsed) => Ok(Value::from_serialize(parsed)),
+        Err(e) => Err(invalid_operation(format!("Couldn't parse JSON: {e}"))),
+    }
+}
+
+fn to_json_filter(value: &Value) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(|e| invalid_operation(format!("Couldn't write JSON: {e}")))
+}
+
+fn from_yaml_filter(value: String) -> Result<Value, Error> {
+    match serde_yaml::from_str::<serde_yaml::Value>(&value) {
+        Ok(parsed) => Ok(Value::from_serialize(parsed)),
+        Err(e) => Err(invalid_operation(format!("Couldn't parse YAML: {e}"))),
+    }
+}
+
+// Values marked as safe, or already quoted by a filter, are written as they are.
+fn write_quoted(out: &mut Output, value: &Value, quote: fn(&str) -> String) -> Result<(), Error> {
+    let text: String = if value.is_safe() || value.is_undefined() {
+        value.to_string()
+    } else {
+        quote(&value.to_string())
+    };
+
+    out.write_str(&text).map_err(Error::from)
+}
+
+// The template library is loaded from `template_dir`, so commands can include or import from it.
+pub fn environment(shortcut: &Shortcut, template_dir: &Path) -> Environment<'static> {
+    let mut env = Environment::new();
+    let escape: Escape = shortcut.autoescape.unwrap_or_default();
+
+    env.set_undefined_behavior(shortcut.undefined.unwrap_or_default().behavior());
+
+    env.add_filter("shell_quote", shell_quote_filter);
+    env.add_filter("sq", shell_quote_filter);
+    env.add_filter("join_quoted", join_quoted_filter);
+    env.add_filter("env_quote", env_quote_filter);
+    env.add_filter("powershell_quote", powershell_quote_filter);
+    env.add_filter("basename", basename_filter);
+    env.add_filter("dirname", dirname_filter);
+    env.add_filter("abspath", abspath_filter);
+    env.add_filter("regex_replace", regex_replace_filter);
+    env.add_filter("from_json", from_json_filter);
+    env.add_filter("to_json", to_json_filter);
+    env.add_filter("from_yaml", from_yaml_filter);
+
+    env.add_function("now", now_function);
+    env.add_function("uuid", uuid_function);
+    env.add_function("hostname", hostname_function);
+    env.add_function("cwd", cwd_function);
+    env.add_function("git_root", git_root_function);
+    env.add_function("read_file", read_file_function);
+    env.add_function("env_or", env_or_function);
+
+    env.set_loader(path_loader(template_dir));
+
+    // Commands and the template library are escaped. Text such as argv items is left alone.
+    env.set_auto_escape_callback(move |name| match name {
+        TEXT_TEMPLATE => AutoEscape::None,
+        _ => escape.auto_escape(),
+    });
+
+    env.set_formatter(|out, state, value| match state.auto_escape() {
+        AutoEscape::Custom(SHELL_ESCAPE) => write_quoted(out, value, shell_quote),
+        AutoEscape::Custom(POWERSHELL_ESCAPE) => write_quoted(out, value, powershell_quote),
+        _ => escape_formatter(out, state, value),
+    });
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shortcut::Shortcut;
+    use crate::templating::{
+        env_quote, environment, powershell_quote, shell_quote, Escape, Placeholder, Undefined,
+        COMMAND_TEMPLATE, TEXT_TEMPLATE,
+    };
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+    use tmp_env::set_var;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("simple-value_1.0"), "simple-value_1.0");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("hello world"), "'hello world'");
+        assert_eq!(shell_quote("it's"), r#"'it'"'"'s'"#);
+        assert_eq!(shell_quote("$(rm -rf /)"), "'$(rm -rf /)'");
+    }
+
+    #[test]
+    fn test_powershell_quote() {
+        assert_eq!(powershell_quote("it's"), "'it''s'");
+        assert_eq!(powershell_quote("$env:PATH"), "'$env:PATH'");
+    }
+
+    #[test]
+    fn test_env_quote() {
+        assert_eq!(env_quote("plain"), r#""plain""#);
+        assert_eq!(env_quote(r#"a "$b" `c` \d"#), r#""a \"\$b\" \`c\` \\d""#);
+    }
+
+    #[test]
+    fn test_environment_filters() {
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        let ctx = HashMap::from([("msg", vec!["it's", "$(whoami)", "plain"])]);
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "echo {{ msg[0]|sq }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, r#"echo 'it'"'"'s'"#);
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "echo {{ msg|join_quoted }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "echo 'it'\"'\"'s' '$(whoami)' plain");
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "X={{ msg[1]|env_quote }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, r#"X="\$(whoami)""#);
+    }
+
+    #[test]
+    fn test_environment_autoescape() {
+        let shortcut = Shortcut {
+            autoescape: Some(Escape::Shell),
+            ..Default::default()
+        };
+        let env = environment(&shortcut, Path::new("templates"));
+        let ctx = HashMap::from([("msg", "$(whoami)")]);
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "echo {{ msg }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "echo '$(whoami)'");
+
+        // Already quoted values are not quoted twice.
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "echo {{ msg|sq }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "echo '$(whoami)'");
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "echo {{ msg|safe }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "echo $(whoami)");
+
+        // Only commands are escaped.
+        let rendered = env
+            .render_named_str(TEXT_TEMPLATE, "{{ msg }}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "$(whoami)");
+    }
+
+    #[test]
+    fn test_environment_undefined() {
+        let ctx = HashMap::from([("args", HashMap::from([("binary", "python")]))]);
+
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        assert!(env
+            .render_named_str(COMMAND_TEMPLATE, "{{ args.bnary }} -V", &ctx)
+            .is_err());
+        assert_eq!(
+            env.render_named_str(COMMAND_TEMPLATE, "{{ args.bnary|default('py') }}", &ctx)
+                .unwrap(),
+            "py"
+        );
+
+        let shortcut = Shortcut {
+            undefined: Some(Undefined::Lenient),
+            ..Default::default()
+        };
+        let env = environment(&shortcut, Path::new("templates"));
+        assert_eq!(
+            env.render_named_str(COMMAND_TEMPLATE, "{{ args.bnary }} -V", &ctx)
+                .unwrap(),
+            " -V"
+        );
+    }
+
+    #[test]
+    fn test_environment_template_library() {
+        let tmp_dir = TempDir::new("shortcut_alias").expect("Couldn't setup tempdir.");
+        fs::write(
+            tmp_dir.path().join("docker.j2"),
+            "{% macro login(registry) %}docker login {{ registry }}{% endmacro %}",
+        )
+        .expect("Couldn't write to temporary file.");
+        fs::write(
+            tmp_dir.path().join("context.j2"),
+            "kubectl config use-context {{ ctx }}",
+        )
+        .expect("Couldn't write to temporary file.");
+
+        let env = environment(&Shortcut::default(), tmp_dir.path());
+        let ctx = HashMap::from([("ctx", "staging")]);
+
+        let rendered = env
+            .render_named_str(
+                COMMAND_TEMPLATE,
+                "{% import 'docker.j2' as docker %}{{ docker.login('ghcr.io') }}",
+                &ctx,
+            )
+            .unwrap();
+        assert_eq!(rendered, "docker login ghcr.io");
+
+        let rendered = env
+            .render_named_str(COMMAND_TEMPLATE, "{% include 'context.j2' %}", &ctx)
+            .unwrap();
+        assert_eq!(rendered, "kubectl config use-context staging");
+
+        assert!(env
+            .render_named_str(COMMAND_TEMPLATE, "{% include 'missing.j2' %}", &ctx)
+            .is_err());
+
+        tmp_dir.close().expect("Couldn't close temp_dir.");
+    }
+
+    #[test]
+    fn test_environment_functions() {
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        let render = |source: &str| {
+            env.render_named_str(COMMAND_TEMPLATE, source, ())
+                .expect("Couldn't render template.")
+        };
+
+        assert_eq!(render("{{ now('%Y') }}").len(), 4);
+        assert!(env
+            .render_named_str(COMMAND_TEMPLATE, "{{ now('%Q') }}", ())
+            .is_err());
+        assert_eq!(render("{{ uuid() }}").len(), 36);
+        assert_ne!(render("{{ uuid() }}"), render("{{ uuid() }}"));
+        assert!(!render("{{ hostname() }}").is_empty());
+        assert_eq!(
+            render("{{ cwd() }}"),
+            std::env::current_dir().unwrap().display().to_string()
+        );
+        if let Ok(root) = env.render_named_str(COMMAND_TEMPLATE, "{{ git_root() }}", ()) {
+            assert!(Path::new(&root).join(".git").exists());
+        };
+        assert!(render("{{ read_file('Cargo.toml') }}").contains("shortcut-alias"));
+
+        {
+            let _tmp_env = set_var("SA_TEMPLATE_TEST", "set");
+            assert_eq!(render("{{ env_or('SA_TEMPLATE_TEST', 'unset') }}"), "set");
+        }
+        assert_eq!(
+            render("{{ env_or('SA_TEMPLATE_MISSING', 'unset') }}"),
+            "unset"
+        );
+    }
+
+    #[test]
+    fn test_environment_path_filters() {
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        let render = |source: &str| {
+            env.render_named_str(COMMAND_TEMPLATE, source, ())
+                .expect("Couldn't render template.")
+        };
+
+        assert_eq!(
+            render("{{ '/srv/app/config.yaml'|basename }}"),
+            "config.yaml"
+        );
+        assert_eq!(render("{{ '/srv/app/config.yaml'|dirname }}"), "/srv/app");
+        assert_eq!(
+            render("{{ 'Cargo.toml'|abspath }}"),
+            std::env::current_dir()
+                .unwrap()
+                .join("Cargo.toml")
+                .display()
+                .to_string()
+        );
+        assert_eq!(
+            render("{{ 'release/1.2.3'|regex_replace('[^0-9.]', '') }}"),
+            "1.2.3"
+        );
+    }
+
+    #[test]
+    fn test_environment_data_filters() {
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        let render = |source: &str| {
+            env.render_named_str(COMMAND_TEMPLATE, source, ())
+                .expect("Couldn't render template.")
+        };
+
+        assert_eq!(
+            render(r#"{{ ('{"items": [1, 2]}'|from_json).items[1] }}"#),
+            "2"
+        );
+        assert_eq!(render("{{ ('name: app\nport: 80'|from_yaml).port }}"), "80");
+        assert_eq!(render("{{ {'a': [1, 'b']}|to_json }}"), r#"{"a":[1,"b"]}"#);
+        assert!(env
+            .render_named_str(COMMAND_TEMPLATE, "{{ 'nope'|from_json }}", ())
+            .is_err());
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let env = environment(&Shortcut::default(), Path::new("templates"));
+        let ctx = HashMap::from([("build", Placeholder::value("commands.build".to_string()))]);
+
+        let rendered = env
+            .render_named_str(
+                COMMAND_TEMPLATE,
+                "{{ build.output }} {{ build.data['items'][0].name }}{% for i in build.data %}x{% endfor %}",
+                &ctx,
+            )
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "<commands.build.output> <commands.build.data.items.0.name>"
+        );
+    }
+}